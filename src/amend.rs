@@ -0,0 +1,292 @@
+//! Changing the most recent clockin in place
+
+use crate::clockin::{self, ClockAction, Clockin, LineClockAction};
+use crate::err::TokErr;
+use crate::gob::{self, do_quotes, tag_items};
+use crate::s_time::STime;
+use gobble::Parser;
+
+/// Replace the bytes start..end of a file with text
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+impl Edit {
+    fn insert(at: usize, text: String) -> Self {
+        Edit {
+            start: at,
+            end: at,
+            text,
+        }
+    }
+}
+
+/// Edits must not overlap
+pub fn apply_edits(s: &str, edits: &[Edit]) -> String {
+    let mut edits: Vec<&Edit> = edits.iter().collect();
+    edits.sort_by_key(|e| (e.start, e.end));
+    let mut res = String::new();
+    let mut pos = 0;
+    for e in edits {
+        res.push_str(&s[pos..e.start]);
+        res.push_str(&e.text);
+        pos = e.end;
+    }
+    res.push_str(&s[pos..]);
+    res
+}
+
+/// The lines that differ between two versions of a file, in diff style
+pub fn show_changes(old: &str, new: &str) -> String {
+    let ol: Vec<&str> = old.lines().collect();
+    let nl: Vec<&str> = new.lines().collect();
+    let pre = ol.iter().zip(&nl).take_while(|(a, b)| a == b).count();
+    let suf = ol[pre..]
+        .iter()
+        .rev()
+        .zip(nl[pre..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut res = String::new();
+    for (i, l) in ol.iter().enumerate().take(ol.len() - suf).skip(pre) {
+        res.push_str(&format!("- {:>5} | {}\n", i + 1, l));
+    }
+    for (i, l) in nl.iter().enumerate().take(nl.len() - suf).skip(pre) {
+        res.push_str(&format!("+ {:>5} | {}\n", i + 1, l));
+    }
+    res
+}
+
+/// The parts of the most recent clockin to change, None leaves it as it is
+#[derive(Clone, Debug, Default)]
+pub struct Amend {
+    pub job: Option<String>,
+    pub time: Option<STime>,
+    pub tags: Option<Vec<String>>,
+    pub note: Option<String>,
+    pub end: Option<STime>,
+}
+
+/// Byte offset of every line start
+fn line_starts(s: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(s.match_indices('\n').map(|(i, _)| i + 1))
+        .collect()
+}
+
+/// The byte range of an action in the file
+fn action_span(s: &str, starts: &[usize], ac: &LineClockAction) -> Result<(usize, usize), TokErr> {
+    let ls = starts[ac.line];
+    let start = s[ls..]
+        .char_indices()
+        .nth(ac.col)
+        .map(|(b, _)| ls + b)
+        .unwrap_or_else(|| s.len());
+    let (rest, _) = gob::ClockACTION
+        .parse_sn(&s[start..])
+        .map_err(|e| e.strung())?;
+    Ok((start, s.len() - rest.len()))
+}
+
+/// Work out the edits that make the change to the last clockin,
+/// found by the line it was read from.
+pub fn plan_amend(s: &str, am: &Amend) -> Result<Vec<Edit>, TokErr> {
+    if let Some(n) = &am.note {
+        if n.contains([',', '\n', '\r']) {
+            return Err(TokErr::from("Notes cannot contain commas or newlines"));
+        }
+    }
+    let actions = gob::line_clock_actions()
+        .parse_s(s)
+        .map_err(|e| e.strung())?;
+    let idat = clockin::read_string(s)?
+        .clocks
+        .into_iter()
+        .rev()
+        .find_map(|c| match c {
+            Clockin::In(d) => Some(d),
+            _ => None,
+        })
+        .ok_or_else(|| TokErr::from("No clockin to amend"))?;
+
+    let a = actions
+        .iter()
+        .rposition(|ac| {
+            ac.line == idat.line
+                && matches!(ac.action, ClockAction::In(_) | ClockAction::InOut(_, _))
+        })
+        .ok_or_else(|| TokErr::from("Could not find the clockin action"))?;
+
+    //the action that ended the session if any
+    let ended_by = match actions[a].action {
+        ClockAction::InOut(_, o) => Some((a, o)),
+        _ => actions[a + 1..]
+            .iter()
+            .enumerate()
+            .find_map(|(i, ac)| match ac.action {
+                ClockAction::Out(o) => Some((a + 1 + i, o)),
+                _ => None,
+            }),
+    };
+
+    let starts = line_starts(s);
+    let mut edits = Vec::new();
+    let (a_start, a_end) = action_span(s, &starts, &actions[a])?;
+
+    //times
+    let n_in = am.time.unwrap_or(idat.time);
+    let n_out = match (am.end, ended_by) {
+        (Some(e), Some(_)) => Some(e),
+        (Some(_), None) => {
+            return Err(TokErr::from(
+                "The last session is still open, use -o -a <time> to clock out",
+            ))
+        }
+        (None, eb) => eb.map(|(_, o)| o),
+    };
+    if let Some(o) = n_out {
+        if o < n_in {
+            return Err(TokErr::NegativeTime);
+        }
+    }
+    match ended_by {
+        Some((e, _)) if e == a => {
+            if am.time.is_some() || am.end.is_some() {
+                let o = n_out.unwrap_or(n_in);
+                edits.push(Edit {
+                    start: a_start,
+                    end: a_end,
+                    text: format!("{}-{}", n_in, o),
+                });
+            }
+        }
+        _ => {
+            if am.time.is_some() {
+                edits.push(Edit {
+                    start: a_start,
+                    end: a_end,
+                    text: n_in.to_string(),
+                });
+            }
+            if let (Some(e), Some((ei, _))) = (am.end, ended_by) {
+                let (st, fin) = action_span(s, &starts, &actions[ei])?;
+                edits.push(Edit {
+                    start: st,
+                    end: fin,
+                    text: format!("-{}", e),
+                });
+            }
+        }
+    }
+
+    //job and tags go in just before the clockin
+    let mut items = Vec::new();
+    if let Some(j) = &am.job {
+        if *j != idat.job {
+            //change the job in place if it was set on this line for this clockin only
+            let set_job = actions[..a]
+                .iter()
+                .rposition(|ac| {
+                    !matches!(
+                        ac.action,
+                        ClockAction::AddTag(_) | ClockAction::ClearTags(_)
+                    )
+                })
+                .filter(|&j| {
+                    actions[j].line == idat.line
+                        && matches!(actions[j].action, ClockAction::SetJob(_))
+                });
+            match set_job {
+                Some(ji) => {
+                    let (st, fin) = action_span(s, &starts, &actions[ji])?;
+                    edits.push(Edit {
+                        start: st,
+                        end: fin,
+                        text: do_quotes(j),
+                    });
+                }
+                None => items.push(do_quotes(j)),
+            }
+        }
+    }
+    if let Some(t) = &am.tags {
+        if *t != idat.tags {
+            items.extend(tag_items(t));
+        }
+    }
+    if !items.is_empty() {
+        edits.push(Edit::insert(a_start, format!("{},", items.join(","))));
+    }
+
+    if let Some(n) = &am.note {
+        let old = actions[a + 1..]
+            .iter()
+            .take_while(|ac| ac.line == idat.line)
+            .find(|ac| matches!(ac.action, ClockAction::Note(_)));
+        match (old, n.is_empty()) {
+            (Some(ac), _) => {
+                let (st, fin) = action_span(s, &starts, ac)?;
+                let text = if n.is_empty() {
+                    String::new()
+                } else {
+                    format!("#{}", n)
+                };
+                edits.push(Edit {
+                    start: st,
+                    end: fin,
+                    text,
+                });
+            }
+            (None, false) => {
+                let ls = starts[idat.line];
+                let le = s[ls..]
+                    .find(['\n', '\r'])
+                    .map(|i| ls + i)
+                    .unwrap_or_else(|| s.len());
+                edits.push(Edit::insert(le, format!(",#{}", n)));
+            }
+            (None, true) => {}
+        }
+    }
+
+    if edits.is_empty() {
+        return Err(TokErr::from("Nothing to change"));
+    }
+
+    //make sure the result still reads
+    let res = apply_edits(s, &edits);
+    clockin::pair_sessions(clockin::read_string(&res)?.clocks)?;
+    Ok(edits)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    pub fn amend_changes_last_clockin() {
+        let s = fixture::DEMO;
+        let am = Amend {
+            job: Some("car wash".to_string()),
+            time: Some(STime::new(14, 30)),
+            tags: Some(vec!["outside".to_string()]),
+            note: Some("soapy".to_string()),
+            end: Some(STime::new(16, 15)),
+        };
+        let res = apply_edits(s, &plan_amend(s, &am).unwrap());
+        assert_eq!(
+            show_changes(s, &res),
+            "-    12 |     programming,14:00\n-    13 |   -16:00\n\
+             +    12 |     \"car wash\",__outside,14:30,#soapy\n+    13 |   -16:15\n"
+        );
+        let sessions = fixture::sessions(&res);
+        let (last, out) = sessions.last().unwrap();
+        assert_eq!(last.note, Some("soapy".to_string()));
+        assert_eq!(last.tags, vec!["outside".to_string()]);
+        assert_eq!(*out, STime::new(16, 15));
+    }
+}
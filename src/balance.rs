@@ -0,0 +1,197 @@
+//! Working hours targets, and how far ahead or behind of them the sessions are
+
+use crate::clockin::Session;
+use crate::err::TokErr;
+use crate::leave::{self, Leave};
+use crate::report::{cell, duration, signed, week_of, Format, Table};
+use crate::s_time::STime;
+use chrono::naive::NaiveDate;
+use chrono::Datelike;
+use std::collections::BTreeMap;
+
+/// Config keys for the per weekday overrides, "target_mon" etc
+pub const DAY_KEYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// Hours expected each weekday, with days off expecting none
+#[derive(Clone, Debug, PartialEq)]
+pub struct Targets {
+    pub days: [STime; 7],
+    pub days_off: Vec<NaiveDate>,
+}
+
+impl Targets {
+    /// A daily target applies Monday to Friday, otherwise a weekly target is spread over them.
+    /// Any weekday can then be set on its own
+    pub fn new(
+        day: Option<STime>,
+        week: Option<STime>,
+        overrides: [Option<STime>; 7],
+        days_off: Vec<NaiveDate>,
+    ) -> Result<Self, TokErr> {
+        let weekday = match (day, week) {
+            (Some(d), _) => Some(d),
+            (None, Some(w)) => Some(STime::new(0, w.as_mins() / 5)),
+            (None, None) => None,
+        };
+        if weekday.is_none() && overrides.iter().all(|o| o.is_none()) {
+            return Err(TokErr::from(
+                "No working hours target set, add target_day or target_week to the config",
+            ));
+        }
+        let mut days = [STime::new(0, 0); 7];
+        for (i, d) in days.iter_mut().enumerate() {
+            *d = match (overrides[i], i) {
+                (Some(t), _) => t,
+                (None, 0..=4) => weekday.unwrap_or_default(),
+                _ => STime::new(0, 0),
+            };
+        }
+        Ok(Targets { days, days_off })
+    }
+
+    pub fn for_day(&self, d: NaiveDate) -> STime {
+        match self.days_off.contains(&d) {
+            true => STime::new(0, 0),
+            false => self.days[d.weekday().num_days_from_monday() as usize],
+        }
+    }
+}
+
+/// Target, time done and leave credited for every day from start to end
+pub fn days(
+    sessions: &[Session],
+    t: &Targets,
+    lv: &[Leave],
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Vec<(NaiveDate, STime, STime, STime)> {
+    let mut done: BTreeMap<NaiveDate, STime> = BTreeMap::new();
+    for s in sessions {
+        *done.entry(s.0.date).or_default() += duration(s);
+    }
+    let mut res = Vec::new();
+    let mut d = start;
+    while d <= end {
+        let target = t.for_day(d);
+        res.push((
+            d,
+            target,
+            done.get(&d).copied().unwrap_or_default(),
+            leave::credit(lv, d, target),
+        ));
+        d = d.succ();
+    }
+    res
+}
+
+/// Target against actual and leave per day or week, with the running balance
+pub fn table(
+    sessions: &[Session],
+    t: &Targets,
+    lv: &[Leave],
+    start: NaiveDate,
+    end: NaiveDate,
+    weekly: bool,
+) -> Table {
+    let zero = STime::new(0, 0);
+    let mut rows: Vec<(String, STime, STime, STime)> = Vec::new();
+    for (d, target, actual, off) in days(sessions, t, lv, start, end) {
+        if weekly {
+            let label = week_of(d).0.format("w/c %d/%m/%Y").to_string();
+            match rows.last_mut() {
+                Some(r) if r.0 == label => {
+                    r.1 += target;
+                    r.2 += actual;
+                    r.3 += off;
+                }
+                _ => rows.push((label, target, actual, off)),
+            }
+        } else if target != zero || actual != zero || off != zero {
+            rows.push((d.format("%a %d/%m/%Y").to_string(), target, actual, off));
+        }
+    }
+    let head = if weekly { "Week" } else { "Day" };
+    let mut tab = Table::new(&[head, "Target", "Actual", "Leave", "Diff", "Balance"]);
+    let (mut t_target, mut t_actual, mut t_off) = (zero, zero, zero);
+    for (label, target, actual, off) in rows {
+        t_target += target;
+        t_actual += actual;
+        t_off += off;
+        tab.rows.push(vec![
+            label,
+            target.to_string(),
+            actual.to_string(),
+            cell(off),
+            signed(actual + off - target),
+            signed(t_actual + t_off - t_target),
+        ]);
+    }
+    tab.foot.push(vec![
+        "Total".to_string(),
+        t_target.to_string(),
+        t_actual.to_string(),
+        t_off.to_string(),
+        signed(t_actual + t_off - t_target),
+        String::new(),
+    ]);
+    tab
+}
+
+pub fn render(
+    sessions: &[Session],
+    t: &Targets,
+    lv: &[Leave],
+    start: NaiveDate,
+    end: NaiveDate,
+    weekly: bool,
+    f: Format,
+) -> String {
+    table(sessions, t, lv, start, end, weekly).render(f)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    pub fn balance_runs_over_days() {
+        let s = "21/01/2019\n  work,9:00-17:00\n22/01/2019\n  work,9:00-15:00\n\
+                 26/01/2019\n  work,10:00-11:00\n";
+        let sessions = fixture::sessions(s);
+        let off = fixture::read("25/01/2019,!sick:2:00").0.leave;
+        let mut over = [None; 7];
+        over[4] = Some(STime::new(4, 0));
+        let t = Targets::new(
+            Some(STime::new(7, 0)),
+            None,
+            over,
+            vec![NaiveDate::from_ymd(2019, 1, 24)],
+        )
+        .unwrap();
+        let start = NaiveDate::from_ymd(2019, 1, 21);
+        let tab = table(
+            &sessions,
+            &t,
+            &[],
+            start,
+            start + chrono::Duration::days(6),
+            false,
+        );
+        //the day off and sunday are left out
+        assert_eq!(tab.rows.len(), 5);
+        assert_eq!(tab.rows[1][5], "+00:00");
+        assert_eq!(tab.rows[2][5], "-07:00");
+        assert_eq!(tab.foot[0][4], "-10:00");
+        let wk = table(
+            &sessions,
+            &t,
+            &off,
+            start,
+            start + chrono::Duration::days(6),
+            true,
+        );
+        assert_eq!(wk.rows[0][1], "25:00");
+        assert_eq!(wk.rows[0][4], "-08:00");
+    }
+}
@@ -0,0 +1,209 @@
+//! Nested subtotals of the filtered sessions, eg by month then job
+
+use crate::clockin::Session;
+use crate::err::TokErr;
+use crate::group::{self, Groups};
+use crate::report::{duration, Format, Table};
+use crate::s_time::STime;
+use chrono::Datelike;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum By {
+    Day,
+    Week,
+    Month,
+    Year,
+    Job,
+    Tag,
+    Group,
+}
+
+impl FromStr for By {
+    type Err = TokErr;
+    fn from_str(s: &str) -> Result<Self, TokErr> {
+        match s.trim().to_lowercase().as_ref() {
+            "day" => Ok(By::Day),
+            "week" => Ok(By::Week),
+            "month" => Ok(By::Month),
+            "year" => Ok(By::Year),
+            "job" => Ok(By::Job),
+            "tag" => Ok(By::Tag),
+            "group" => Ok(By::Group),
+            _ => Err(TokErr::Mess(format!(
+                "Cannot break down by \"{}\", use day, week, month, year, job, tag or group",
+                s
+            ))),
+        }
+    }
+}
+
+impl By {
+    pub fn name(&self) -> &'static str {
+        match self {
+            By::Day => "Day",
+            By::Week => "Week",
+            By::Month => "Month",
+            By::Year => "Year",
+            By::Job => "Job",
+            By::Tag => "Tag",
+            By::Group => "Group",
+        }
+    }
+
+    /// The buckets a session falls in as (sort key, label).
+    /// Tags and groups can put a session in more than one
+    pub fn keys(&self, s: &Session, groups: &Groups) -> Vec<(String, String)> {
+        let d = s.0.date;
+        let same = |k: String| vec![(k.clone(), k)];
+        match self {
+            By::Day => vec![(
+                d.format("%Y-%m-%d").to_string(),
+                d.format("%d/%m/%Y").to_string(),
+            )],
+            By::Week => {
+                let w = d.iso_week();
+                same(format!("{} W{:02}", w.year(), w.week()))
+            }
+            By::Month => vec![(d.format("%Y-%m").to_string(), d.format("%m/%Y").to_string())],
+            By::Year => same(d.year().to_string()),
+            By::Job => same(s.0.job.clone()),
+            By::Tag => match s.0.tags.len() {
+                0 => same("(untagged)".to_string()),
+                _ => s.0.tags.iter().map(|t| (t.clone(), t.clone())).collect(),
+            },
+            By::Group => {
+                let res: Vec<(String, String)> = group::groups_of(groups, &s.0)
+                    .into_iter()
+                    .map(|k| (k.clone(), k))
+                    .collect();
+                match res.len() {
+                    0 => same("(no group)".to_string()),
+                    _ => res,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Bucket {
+    pub label: String,
+    pub total: STime,
+    pub count: usize,
+    pub children: BTreeMap<String, Bucket>,
+}
+
+impl Bucket {
+    pub fn new(label: &str) -> Self {
+        Bucket {
+            label: label.to_string(),
+            total: STime::new(0, 0),
+            count: 0,
+            children: BTreeMap::new(),
+        }
+    }
+
+    pub fn average(&self) -> STime {
+        match self.count {
+            0 => STime::new(0, 0),
+            n => STime::new(0, self.total.as_mins() / n as isize),
+        }
+    }
+
+    fn add(&mut self, s: &Session, dims: &[By], groups: &Groups) {
+        self.total += duration(s);
+        self.count += 1;
+        if let Some((d, rest)) = dims.split_first() {
+            for (k, label) in d.keys(s, groups) {
+                self.children
+                    .entry(k)
+                    .or_insert_with(|| Bucket::new(&label))
+                    .add(s, rest, groups);
+            }
+        }
+    }
+
+    fn push_rows(&self, depth: usize, levels: usize, tab: &mut Table) {
+        for c in self.children.values() {
+            let mut row = vec![String::new(); levels];
+            row[depth] = c.label.clone();
+            row.push(c.total.to_string());
+            row.push(c.count.to_string());
+            row.push(c.average().to_string());
+            tab.rows.push(row);
+            c.push_rows(depth + 1, levels, tab);
+        }
+    }
+}
+
+/// The whole tree of subtotals, the root holds the grand total
+pub fn breakdown(sessions: &[Session], dims: &[By], groups: &Groups) -> Bucket {
+    let mut root = Bucket::new("Total");
+    for s in sessions {
+        root.add(s, dims, groups);
+    }
+    root
+}
+
+/// One column for each level, then the total, count and average for each bucket
+pub fn table(root: &Bucket, dims: &[By]) -> Table {
+    let mut head: Vec<&str> = dims.iter().map(|d| d.name()).collect();
+    head.extend(&["Total", "Sessions", "Average"]);
+    let mut tab = Table::new(&head);
+    root.push_rows(0, dims.len(), &mut tab);
+    let mut foot = vec![String::new(); dims.len()];
+    foot[0] = root.label.clone();
+    foot.push(root.total.to_string());
+    foot.push(root.count.to_string());
+    foot.push(root.average().to_string());
+    tab.foot.push(foot);
+    tab
+}
+
+/// Time per tag, then per tag within each job, untagged time included
+pub fn tag_report(sessions: &[Session], f: Format) -> String {
+    let groups = Groups::new();
+    let mut res = String::new();
+    for (title, dims) in &[
+        ("Tags", vec![By::Tag]),
+        ("Tags by job", vec![By::Job, By::Tag]),
+    ] {
+        let tab = table(&breakdown(sessions, dims, &groups), dims).render(f);
+        match f {
+            Format::Csv => res.push_str(&format!("{}\n", tab)),
+            Format::Markdown => res.push_str(&format!("### {}\n\n{}\n", title, tab)),
+            Format::Text => res.push_str(&format!("{}\n{}\n", title, tab)),
+            Format::Html => res.push_str(&format!("<h2>{}</h2>\n{}", title, tab)),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    pub fn breakdown_nests_subtotals() {
+        let (data, sessions, _) = fixture::read(fixture::DEMO);
+        let root = breakdown(&sessions, &[By::Day, By::Group], &data.groups);
+        assert_eq!(root.total, STime::new(7, 20));
+        let day = &root.children["2019-01-23"];
+        assert_eq!(day.count, 4);
+        assert_eq!(day.children["home_jobs"].total, STime::new(3, 20));
+        assert_eq!(day.children["(no group)"].average(), STime::new(1, 0));
+    }
+
+    #[test]
+    pub fn breakdown_splits_the_year_end() {
+        let (data, sessions, _) = fixture::read(fixture::EDGES);
+        let root = breakdown(&sessions, &[By::Year, By::Group], &data.groups);
+        assert_eq!(root.total, STime::new(3, 50));
+        let play = &root.children["2019"].children["play"];
+        assert_eq!((play.total, play.count), (STime::new(2, 45), 2));
+        assert_eq!(root.children["2020"].total, STime::new(0, 5));
+    }
+}
@@ -0,0 +1,204 @@
+//! Hours set aside for a job or group, in total or per week or month
+
+use crate::clockin::{InData, Session};
+use crate::group::{self, Groups};
+use crate::report::{duration, week_of, Format, Table};
+use crate::s_time::STime;
+use crate::tree;
+use chrono::naive::NaiveDate;
+use chrono::Datelike;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Period {
+    Total,
+    Week,
+    Month,
+}
+
+/// Written "+name:40:00" or "+name:10:00/week" or "/month"
+#[derive(Clone, Debug, PartialEq)]
+pub struct Budget {
+    pub time: STime,
+    pub period: Period,
+}
+
+pub type Budgets = BTreeMap<String, Budget>;
+
+/// A budget named for a group covers the group, otherwise the job and every job under it
+pub fn covers(groups: &Groups, name: &str, d: &InData) -> bool {
+    match groups.contains_key(name) {
+        true => group::in_group(groups, name, d),
+        false => tree::in_subtree(&d.job, name),
+    }
+}
+
+/// The first and last days of the period containing today
+pub fn period_range(p: Period, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+    match p {
+        Period::Total => None,
+        Period::Week => Some(week_of(today)),
+        Period::Month => {
+            let st = NaiveDate::from_ymd(today.year(), today.month(), 1);
+            let next = match today.month() {
+                12 => NaiveDate::from_ymd(today.year() + 1, 1, 1),
+                m => NaiveDate::from_ymd(today.year(), m + 1, 1),
+            };
+            Some((st, next.pred()))
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Usage {
+    pub name: String,
+    pub budget: Budget,
+    pub used: STime,
+}
+
+impl Usage {
+    pub fn over(&self) -> bool {
+        self.used > self.budget.time
+    }
+
+    /// What is left, or how far over with a leading "-"
+    pub fn remaining(&self) -> String {
+        match self.over() {
+            true => format!("-{}", self.used - self.budget.time),
+            false => (self.budget.time - self.used).to_string(),
+        }
+    }
+
+    pub fn percent(&self) -> isize {
+        match self.budget.time.as_mins() {
+            0 => 0,
+            b => self.used.as_mins() * 100 / b,
+        }
+    }
+
+    /// One line summary for showing on clockin
+    pub fn describe(&self) -> String {
+        let period = match self.budget.period {
+            Period::Total => "",
+            Period::Week => " this week",
+            Period::Month => " this month",
+        };
+        match self.over() {
+            true => format!(
+                "Budget \"{}\" is over by {}{} ({} of {})",
+                self.name,
+                self.used - self.budget.time,
+                period,
+                self.used,
+                self.budget.time
+            ),
+            false => format!(
+                "Budget \"{}\": {} of {} used{}, {} remaining",
+                self.name,
+                self.used,
+                self.budget.time,
+                period,
+                self.remaining()
+            ),
+        }
+    }
+}
+
+/// Time used against each budget in its current period
+pub fn usage(
+    budgets: &Budgets,
+    groups: &Groups,
+    sessions: &[Session],
+    today: NaiveDate,
+) -> Vec<Usage> {
+    budgets
+        .iter()
+        .map(|(name, b)| {
+            let range = period_range(b.period, today);
+            let used = sessions
+                .iter()
+                .filter(|s| match range {
+                    Some((st, fin)) => s.0.date >= st && s.0.date <= fin,
+                    None => true,
+                })
+                .filter(|s| covers(groups, name, &s.0))
+                .fold(STime::new(0, 0), |a, s| a + duration(s));
+            Usage {
+                name: name.clone(),
+                budget: b.clone(),
+                used,
+            }
+        })
+        .collect()
+}
+
+/// The lines to show when clocking in to d, one for each budget covering it
+pub fn clockin_notes(
+    budgets: &Budgets,
+    groups: &Groups,
+    sessions: &[Session],
+    today: NaiveDate,
+    d: &InData,
+) -> Vec<String> {
+    usage(budgets, groups, sessions, today)
+        .into_iter()
+        .filter(|u| covers(groups, &u.name, d))
+        .map(|u| match u.over() {
+            true => format!("WARNING: {}", u.describe()),
+            false => u.describe(),
+        })
+        .collect()
+}
+
+pub fn render(usages: &[Usage], f: Format) -> String {
+    let mut tab = Table::new(&["Budget", "Period", "Limit", "Used", "Remaining", "%"]);
+    for u in usages {
+        let period = match u.budget.period {
+            Period::Total => "total",
+            Period::Week => "week",
+            Period::Month => "month",
+        };
+        let mut name = u.name.clone();
+        if u.over() {
+            name.push_str(" (over)");
+        }
+        tab.rows.push(vec![
+            name,
+            period.to_string(),
+            u.budget.time.to_string(),
+            u.used.to_string(),
+            u.remaining(),
+            u.percent().to_string(),
+        ]);
+    }
+    tab.render(f)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    pub fn budget_counts_current_period() {
+        let s = "+client_a:3:00/week,+home:1:00\n$home[cook]\n\
+                 14/01/2019\n  client_a/api,9:00-12:00\n\
+                 23/01/2019\n  client_a/api,9:00-10:00\n  client_a,10:00-11:30\n  cook,12:00-13:30\n";
+        let (data, sessions, _) = fixture::read(s);
+        let u = usage(
+            &data.budgets,
+            &data.groups,
+            &sessions,
+            NaiveDate::from_ymd(2019, 1, 24),
+        );
+        assert_eq!(u[0].name, "client_a");
+        assert_eq!(u[0].used, STime::new(2, 30));
+        assert_eq!(u[0].remaining(), "00:30");
+        assert!(u[1].over());
+        assert_eq!(u[1].remaining(), "-00:30");
+        let cook = sessions.last().unwrap().0.clone();
+        let notes = clockin_notes(&data.budgets, &data.groups, &sessions, cook.date, &cook);
+        assert_eq!(notes.len(), 1);
+        assert!(notes[0].starts_with("WARNING: Budget \"home\" is over"));
+    }
+}
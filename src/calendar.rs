@@ -0,0 +1,194 @@
+//! Calendar grids showing how much was worked each day
+
+use crate::chart;
+use crate::clockin::Session;
+use crate::s_time::STime;
+use chrono::naive::NaiveDate;
+use chrono::{Datelike, Duration};
+use std::collections::BTreeMap;
+
+const LEVELS: [char; 5] = ['·', '░', '▒', '▓', '█'];
+
+/// Time worked on each day with any sessions
+pub fn day_totals(sessions: &[Session]) -> BTreeMap<NaiveDate, STime> {
+    chart::daily(sessions)
+        .into_iter()
+        .filter(|(_, t)| t.as_mins() > 0)
+        .collect()
+}
+
+/// 0 for nothing, then 1 to 4 by quarters of the busiest day
+pub fn level(t: STime, max: STime) -> usize {
+    match (t.as_mins(), max.as_mins()) {
+        (v, _) if v <= 0 => 0,
+        (_, m) if m <= 0 => 0,
+        (v, m) => (((v * 4 + m - 1) / m) as usize).min(4),
+    }
+}
+
+/// Two characters for a day, the level or whole hours rounded
+fn mark(t: STime, max: STime, hours: bool) -> String {
+    match hours {
+        true if t.as_mins() > 0 => format!("{:>2}", (t.as_mins() + 30) / 60),
+        true => " ·".to_string(),
+        false => LEVELS[level(t, max)].to_string().repeat(2),
+    }
+}
+
+fn month_start(d: NaiveDate) -> NaiveDate {
+    NaiveDate::from_ymd(d.year(), d.month(), 1)
+}
+
+fn next_month(d: NaiveDate) -> NaiveDate {
+    match d.month() {
+        12 => NaiveDate::from_ymd(d.year() + 1, 1, 1),
+        m => NaiveDate::from_ymd(d.year(), m + 1, 1),
+    }
+}
+
+/// One month, a row per week Monday first, each day numbered
+pub fn month(
+    first: NaiveDate,
+    days: &BTreeMap<NaiveDate, STime>,
+    max: STime,
+    hours: bool,
+) -> String {
+    let first = month_start(first);
+    let mut res = format!("{}\n", first.format("%B %Y"));
+    res.push_str("Mo    Tu    We    Th    Fr    Sa    Su\n");
+    let mut line = "      ".repeat(first.weekday().num_days_from_monday() as usize);
+    let mut d = first;
+    while d < next_month(first) {
+        let t = days.get(&d).copied().unwrap_or_default();
+        line.push_str(&format!("{:>2} {} ", d.day(), mark(t, max, hours)));
+        if d.weekday().num_days_from_monday() == 6 {
+            res.push_str(line.trim_end());
+            res.push('\n');
+            line.clear();
+        }
+        d = d.succ();
+    }
+    if !line.is_empty() {
+        res.push_str(line.trim_end());
+        res.push('\n');
+    }
+    res
+}
+
+/// A whole year, a row per weekday and a column per week
+pub fn year(y: i32, days: &BTreeMap<NaiveDate, STime>, max: STime, hours: bool) -> String {
+    let jan1 = NaiveDate::from_ymd(y, 1, 1);
+    let start = jan1 - Duration::days(jan1.weekday().num_days_from_monday() as i64);
+    let end = NaiveDate::from_ymd(y, 12, 31);
+    let weeks = ((end - start).num_days() / 7 + 1) as usize;
+    let cw = if hours { 3 } else { 1 };
+
+    let mut head = " ".repeat(4);
+    let mut m = jan1;
+    while m.year() == y {
+        let col = 4 + ((m - start).num_days() / 7) as usize * cw;
+        if head.chars().count() < col {
+            head.push_str(&" ".repeat(col - head.chars().count()));
+        }
+        head.push_str(&m.format("%b").to_string());
+        m = next_month(m);
+    }
+    let mut res = format!("{}\n{}\n", y, head.trim_end());
+    for (wd, name) in ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"]
+        .iter()
+        .enumerate()
+    {
+        let mut line = format!("{} ", name);
+        for w in 0..weeks {
+            let d = start + Duration::days((w * 7 + wd) as i64);
+            if d.year() != y {
+                line.push_str(&" ".repeat(cw));
+                continue;
+            }
+            let t = days.get(&d).copied().unwrap_or_default();
+            match hours {
+                true => line.push_str(&format!("{} ", mark(t, max, true))),
+                false => line.push(LEVELS[level(t, max)]),
+            }
+        }
+        res.push_str(line.trim_end());
+        res.push('\n');
+    }
+    res
+}
+
+/// A month grid for each month with sessions, or a year grid for each year
+pub fn render(sessions: &[Session], whole_year: bool, hours: bool) -> String {
+    let days = day_totals(sessions);
+    let max = days.values().copied().max().unwrap_or_default();
+    let (first, last) = match (days.keys().next(), days.keys().last()) {
+        (Some(f), Some(l)) => (*f, *l),
+        _ => return "No sessions to show\n".to_string(),
+    };
+    let mut res = Vec::new();
+    if whole_year {
+        for y in first.year()..=last.year() {
+            res.push(year(y, &days, max, hours));
+        }
+    } else {
+        let mut m = month_start(first);
+        while m <= last {
+            res.push(month(m, &days, max, hours));
+            m = next_month(m);
+        }
+    }
+    let legend = match hours {
+        true => "Hours worked each day".to_string(),
+        false => format!(
+            "{} none  {} up to a quarter  {} half  {} three quarters  {} up to {}",
+            LEVELS[0], LEVELS[1], LEVELS[2], LEVELS[3], LEVELS[4], max
+        ),
+    };
+    format!("{}\n{}\n", res.join("\n"), legend)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    pub fn calendar_marks_days() {
+        assert_eq!(level(STime::new(1, 0), STime::new(4, 0)), 1);
+        assert_eq!(level(STime::new(1, 1), STime::new(4, 0)), 2);
+        assert_eq!(level(STime::new(4, 0), STime::new(4, 0)), 4);
+        let days = day_totals(&fixture::sessions(fixture::DEMO));
+        let m = month(
+            NaiveDate::from_ymd(2019, 1, 23),
+            &days,
+            STime::new(4, 20),
+            true,
+        );
+        let lines: Vec<&str> = m.lines().collect();
+        assert_eq!(lines[0], "January 2019");
+        //the 1st was a Tuesday
+        assert!(lines[2].starts_with("       1  ·"));
+        assert!(lines[5].contains("23  4 24  3"));
+    }
+
+    #[test]
+    pub fn calendar_days_sit_under_their_weekday() {
+        let days = day_totals(&fixture::sessions(fixture::DEMO));
+        let m = month(
+            NaiveDate::from_ymd(2019, 1, 1),
+            &days,
+            STime::new(4, 20),
+            false,
+        );
+        //columns in chars, as the marks are more than a byte
+        let col = |row: usize, s: &str| {
+            let l = m.lines().nth(row).unwrap();
+            l[..l.find(s).unwrap()].chars().count()
+        };
+        //the 23rd was a Wednesday
+        assert_eq!(col(5, "23"), col(1, "We"));
+        assert_eq!(col(5, "24"), col(1, "Th"));
+        assert_eq!(col(4, "20"), col(1, "Su"));
+        assert_eq!(col(2, "1"), col(1, "Tu") + 1);
+    }
+}
@@ -0,0 +1,125 @@
+//! Text bar charts of the filtered sessions, sized to the terminal
+
+use crate::breakdown::{breakdown, By};
+use crate::clockin::Session;
+use crate::group::Groups;
+use crate::report::duration;
+use crate::s_time::STime;
+use chrono::naive::NaiveDate;
+use std::collections::BTreeMap;
+
+const EIGHTHS: [char; 8] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉', '█'];
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// A bar of v out of max, at most w characters, to an eighth of a character
+pub fn bar(v: isize, max: isize, w: usize) -> String {
+    if v <= 0 || max <= 0 {
+        return String::new();
+    }
+    let eighths = (v * w as isize * 8 / max) as usize;
+    let mut res = "█".repeat(eighths / 8);
+    match eighths % 8 {
+        0 if eighths == 0 => res.push(EIGHTHS[0]),
+        0 => {}
+        n => res.push(EIGHTHS[n - 1]),
+    }
+    res
+}
+
+/// One line per row, labels to the left and times to the right of the bars
+pub fn bars(rows: &[(String, STime)], width: usize) -> String {
+    let lw = rows.iter().map(|r| r.0.chars().count()).max().unwrap_or(0);
+    let max = rows.iter().map(|r| r.1.as_mins()).max().unwrap_or(0);
+    //label, spaces and the time take this much
+    let w = width.saturating_sub(lw + 9).max(1);
+    rows.iter()
+        .map(|(l, t)| {
+            let b = bar(t.as_mins(), max, w);
+            format!("{:<lw$} {:<w$} {}\n", l, b, t, lw = lw, w = w)
+        })
+        .collect()
+}
+
+/// A single line with one block per value, scaled to the biggest
+pub fn sparkline(vals: &[isize]) -> String {
+    let max = vals.iter().copied().max().unwrap_or(0);
+    vals.iter()
+        .map(|&v| match v {
+            0 => ' ',
+            _ if max <= 0 => ' ',
+            _ => SPARKS[((v * 7 / max) as usize).min(7)],
+        })
+        .collect()
+}
+
+/// Time on every day from the first session to the last, including empty days
+pub fn daily(sessions: &[Session]) -> Vec<(NaiveDate, STime)> {
+    let mut done: BTreeMap<NaiveDate, STime> = BTreeMap::new();
+    for s in sessions {
+        *done.entry(s.0.date).or_default() += duration(s);
+    }
+    let (first, last) = match (done.keys().next(), done.keys().last()) {
+        (Some(f), Some(l)) => (*f, *l),
+        _ => return Vec::new(),
+    };
+    let mut res = Vec::new();
+    let mut d = first;
+    while d <= last {
+        res.push((d, done.get(&d).copied().unwrap_or_default()));
+        d = d.succ();
+    }
+    res
+}
+
+/// Bars by the chosen dimension, then a bar and a sparkline for the days
+pub fn render(sessions: &[Session], by: By, groups: &Groups, width: usize) -> String {
+    let root = breakdown(sessions, &[by], groups);
+    let rows: Vec<(String, STime)> = root
+        .children
+        .values()
+        .map(|b| (b.label.clone(), b.total))
+        .collect();
+    let daily = daily(sessions);
+    let days: Vec<(String, STime)> = daily
+        .iter()
+        .map(|(d, t)| (d.format("%a %d/%m/%Y").to_string(), *t))
+        .collect();
+    let spark: Vec<isize> = daily.iter().map(|d| d.1.as_mins()).collect();
+    let ends = match (daily.first(), daily.last()) {
+        (Some(f), Some(l)) => (
+            f.0.format("%d/%m/%Y").to_string(),
+            l.0.format("%d/%m/%Y").to_string(),
+        ),
+        _ => Default::default(),
+    };
+    format!(
+        "Time by {}\n{}\nTime by day\n{}\n{} {} {}\n",
+        by.name().to_lowercase(),
+        bars(&rows, width),
+        bars(&days, width),
+        ends.0,
+        sparkline(&spark),
+        ends.1
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn bars_scale_to_width() {
+        assert_eq!(bar(60, 60, 4), "████");
+        assert_eq!(bar(15, 60, 4), "█");
+        assert_eq!(bar(8, 60, 4), "▌");
+        assert_eq!(bar(1, 60, 4), "▏");
+        assert_eq!(bar(0, 60, 4), "");
+        let rows = vec![
+            ("a".to_string(), STime::new(2, 0)),
+            ("bb".to_string(), STime::new(1, 0)),
+        ];
+        let res = bars(&rows, 21);
+        assert_eq!(res, "a  ██████████ 02:00\nbb █████      01:00\n");
+        assert_eq!(sparkline(&[0, 5, 70]), " ▁█");
+    }
+}
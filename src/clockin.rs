@@ -8,7 +8,10 @@ use gobble::Parser;
 use std::collections::BTreeMap;
 use std::fmt::Display;
 
-use crate::err::TokErr;
+use crate::budget::{Budget, Budgets};
+use crate::err::{LineErr, TokErr};
+use crate::group::{Groups, Member};
+use crate::leave::Leave;
 //use crate::pesto::{LineNum, Pestable, Rule, TimeFile};
 use crate::s_time::STime;
 
@@ -19,7 +22,7 @@ pub struct LineClockAction {
     pub action: ClockAction,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum ClockAction {
     AddTag(String),
     ClearTags(Option<String>), //replacement tag
@@ -29,7 +32,12 @@ pub enum ClockAction {
     SetJob(String),
     SetDate(usize, usize, Option<isize>),
     SetNum(String, isize),
-    DefGroup(String, Vec<String>),
+    DefGroup(String, Vec<Member>),
+    SetBudget(String, Budget),
+    Leave(String, Option<STime>),
+    Note(String),
+    Push, //the next clockin interrupts the current one
+    Pop,  //the next clockin resumes the last interrupted one
 }
 
 use self::ClockAction::*;
@@ -68,7 +76,11 @@ pub enum Clockin {
 
 pub struct AllData {
     pub clocks: Vec<Clockin>,
-    pub groups: BTreeMap<String, Vec<String>>,
+    pub groups: Groups,
+    pub budgets: Budgets,
+    pub leave: Vec<Leave>,
+    /// Clockins interrupted by a push, and not yet popped back to
+    pub stack: Vec<InData>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -78,22 +90,57 @@ pub struct InData {
     pub job: String,
     pub tags: Vec<String>,
     pub line: usize,
+    pub note: Option<String>, //a comment on the same line
 }
 
+/// A clockin and the time it ended
+pub type Session = (InData, STime);
+
 impl Display for InData {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}({} - {})", self.job, self.date, self.time)
     }
 }
 
+/// Follows the date and year lines of a file, so a date without a year can be read
+#[derive(Clone, Debug, Default)]
+pub struct DateWalker {
+    pub date: Option<NaiveDate>,
+    pub year: Option<isize>,
+}
+
+impl DateWalker {
+    /// Returns the new date if the action set one
+    pub fn step(&mut self, ac: &LineClockAction) -> Result<Option<NaiveDate>, LineErr> {
+        let (d, m, y) = match &ac.action {
+            SetDate(d, m, Some(y)) => (*d, *m, *y),
+            SetDate(d, m, None) => match self.year {
+                Some(y) => (*d, *m, y),
+                None => return Err(TokErr::NotSet("date").on_line(ac.line)),
+            },
+            SetNum(k, v) if k == "year" => {
+                self.year = Some(*v);
+                return Ok(None);
+            }
+            _ => return Ok(None),
+        };
+        let date = NaiveDate::from_ymd(y as i32, m as u32, d as u32);
+        self.date = Some(date);
+        Ok(Some(date))
+    }
+}
+
 pub fn read_string(s: &str) -> Result<AllData, TokErr> {
     let mut job = "General".to_string();
     let mut tags = Vec::new();
     let mut date = NaiveDate::from_ymd(1, 1, 1); //consider changing
-    let mut year: Option<isize> = None;
+    let mut dates = DateWalker::default();
 
     let mut c_res = Vec::new();
     let mut groups = BTreeMap::new();
+    let mut budgets = BTreeMap::new();
+    let mut leave = Vec::new();
+    let mut stack = Vec::new();
 
     let c_ac = gob::line_clock_actions()
         .parse_s(s)
@@ -101,27 +148,24 @@ pub fn read_string(s: &str) -> Result<AllData, TokErr> {
     let mut errs = Vec::new();
 
     for ac in c_ac {
+        match dates.step(&ac) {
+            Ok(Some(d)) => date = d,
+            Ok(None) => {}
+            Err(e) => errs.push(e),
+        }
         match ac.action {
             SetJob(j) => job = j,
-            SetDate(d, m, Some(y)) => date = NaiveDate::from_ymd(y as i32, m as u32, d as u32),
-            SetDate(d, m, None) => match year {
-                Some(y) => date = NaiveDate::from_ymd(y as i32, m as u32, d as u32),
-                None => errs.push(TokErr::NotSet("date").on_line(ac.line)),
-            },
+            SetDate(_, _, _) | SetNum(_, _) => {}
             AddTag(s) => tags.push(s.clone()),
             ClearTags(Some(s)) => tags = vec![s],
             ClearTags(None) => tags.clear(),
-            SetNum(k, v) => {
-                if &k == "year" {
-                    year = Some(v);
-                }
-            }
             In(time) => c_res.push(Clockin::In(InData {
                 time,
                 job: job.clone(),
                 tags: tags.clone(),
                 date,
                 line: ac.line,
+                note: None,
             })),
 
             Out(time) => c_res.push(Clockin::Out(time)),
@@ -132,21 +176,107 @@ pub fn read_string(s: &str) -> Result<AllData, TokErr> {
                     tags: tags.clone(),
                     date,
                     line: ac.line,
+                    note: None,
                 }));
                 c_res.push(Clockin::Out(tout));
             }
             DefGroup(k, v) => {
                 groups.insert(k, v);
             }
+            SetBudget(k, b) => {
+                budgets.insert(k, b);
+            }
+            ClockAction::Leave(kind, hours) => match dates.date {
+                Some(date) => leave.push(Leave {
+                    date,
+                    kind,
+                    hours,
+                    line: ac.line,
+                }),
+                None => errs.push(TokErr::NotSet("date").on_line(ac.line)),
+            },
+            Push => {
+                let last_in = c_res.iter().rev().find_map(|c| match c {
+                    Clockin::In(d) => Some(d),
+                    _ => None,
+                });
+                if let Some(d) = last_in {
+                    stack.push(d.clone());
+                }
+            }
+            Pop => {
+                stack.pop();
+            }
+            Note(n) => {
+                let last_in = c_res.iter_mut().rev().find_map(|c| match c {
+                    Clockin::In(d) => Some(d),
+                    _ => None,
+                });
+                if let Some(d) = last_in {
+                    if d.line == ac.line {
+                        d.note = Some(n);
+                    }
+                }
+            }
         }
     }
 
-    if errs.len() > 0 {
+    if !errs.is_empty() {
         Err(TokErr::Lines(errs))
     } else {
         Ok(AllData {
             clocks: c_res,
-            groups: groups,
+            groups,
+            budgets,
+            leave,
+            stack,
         })
     }
 }
+
+/// The (0 based) line of every date, in file order
+pub fn date_lines(s: &str) -> Result<Vec<(usize, NaiveDate)>, TokErr> {
+    let c_ac = gob::line_clock_actions()
+        .parse_s(s)
+        .map_err(|e| e.strung())?;
+    let mut dates = DateWalker::default();
+    let mut res = Vec::new();
+    for ac in c_ac {
+        if let Some(d) = dates.step(&ac)? {
+            res.push((ac.line, d));
+        }
+    }
+    Ok(res)
+}
+
+/// Pairs every clockin with the time it ended.
+/// A clockin is ended by the next clockout, or implicitly by the next clockin.
+/// Returns the finished sessions, and the clockin still open if there is one.
+/// A clockout with no open clockin is warned about and skipped.
+pub fn pair_sessions(clocks: Vec<Clockin>) -> Result<(Vec<Session>, Option<InData>), LineErr> {
+    let mut curr: Option<InData> = None;
+    let mut c_io = Vec::new();
+    for c in clocks {
+        match c {
+            Clockin::In(data) => {
+                if let Some(cin) = curr {
+                    c_io.push((cin, data.time));
+                }
+                curr = Some(data);
+            }
+            Clockin::Out(cout) => {
+                match curr {
+                    Some(data) => {
+                        if cout < data.time {
+                            return Err(TokErr::NegativeTime.on_line(data.line));
+                        }
+                        c_io.push((data, cout));
+                    }
+                    None => eprintln!("Two Out's in a row"),
+                }
+                curr = None;
+            }
+        }
+    }
+    Ok((c_io, curr))
+}
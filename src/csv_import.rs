@@ -0,0 +1,346 @@
+//! Sessions from the CSV exports of other time trackers
+
+use crate::clockin::{InData, Session};
+use crate::err::TokErr;
+use crate::import::Rule;
+use crate::s_time::STime;
+use chrono::naive::{NaiveDate, NaiveDateTime};
+
+/// Splits CSV text into rows of fields, allowing quoted fields with commas, quotes and newlines
+pub fn read_csv(s: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut it = s.chars().peekable();
+    while let Some(c) = it.next() {
+        match (c, quoted) {
+            ('"', true) if it.peek() == Some(&'"') => {
+                it.next();
+                field.push('"');
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.retain(|r| r.iter().any(|f| !f.trim().is_empty()));
+    rows
+}
+
+/// A column by its header or its (1 based) number
+#[derive(Clone, Debug, PartialEq)]
+pub enum Col {
+    Name(String),
+    Num(usize),
+}
+
+/// Which column holds each part of a session.
+/// A duration column can stand in for the end
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Columns {
+    pub date: Option<Col>,
+    pub start: Option<Col>,
+    pub end: Option<Col>,
+    pub duration: Option<Col>,
+    pub job: Option<Col>,
+    pub tags: Option<Col>,
+    pub note: Option<Col>,
+}
+
+impl Columns {
+    /// From a spec like "date=Day,start=From,end=To,job=Project,note=3"
+    pub fn parse(spec: &str) -> Result<Self, TokErr> {
+        let mut res = Columns::default();
+        for part in spec.split(',').filter(|p| !p.trim().is_empty()) {
+            let (k, v) = match part.find('=') {
+                Some(i) => (part[..i].trim(), part[i + 1..].trim()),
+                None => {
+                    return Err(TokErr::Mess(format!(
+                        "Column spec \"{}\" needs a \"=\"",
+                        part
+                    )))
+                }
+            };
+            let col = match v.parse::<usize>() {
+                Ok(n) if n > 0 => Col::Num(n),
+                _ => Col::Name(v.to_string()),
+            };
+            let slot = match k.to_lowercase().as_ref() {
+                "date" => &mut res.date,
+                "start" => &mut res.start,
+                "end" => &mut res.end,
+                "duration" => &mut res.duration,
+                "job" | "project" => &mut res.job,
+                "tags" | "tag" => &mut res.tags,
+                "note" | "description" => &mut res.note,
+                _ => return Err(TokErr::Mess(format!("Unknown column \"{}\"", k))),
+            };
+            *slot = Some(col);
+        }
+        Ok(res)
+    }
+
+    /// Guessed from the header row, for any not already set
+    pub fn guess(&mut self, head: &[String]) {
+        let find = |names: &[&str]| {
+            head.iter()
+                .find(|h| names.contains(&h.trim().to_lowercase().as_ref()))
+                .map(|h| Col::Name(h.clone()))
+        };
+        let fill = |slot: &mut Option<Col>, names: &[&str]| {
+            if slot.is_none() {
+                *slot = find(names);
+            }
+        };
+        fill(&mut self.date, &["date", "day"]);
+        fill(&mut self.start, &["start", "start time", "from", "begin"]);
+        fill(&mut self.end, &["end", "end time", "to", "stop", "finish"]);
+        fill(&mut self.duration, &["duration", "hours", "time"]);
+        fill(&mut self.job, &["job", "project", "task", "activity"]);
+        fill(&mut self.tags, &["tags", "tag", "labels"]);
+        fill(&mut self.note, &["note", "notes", "description", "comment"]);
+    }
+}
+
+fn index(c: &Option<Col>, head: &[String]) -> Option<usize> {
+    match c {
+        Some(Col::Num(n)) => Some(n - 1),
+        Some(Col::Name(n)) => head
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(n.trim())),
+        None => None,
+    }
+}
+
+const DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%d/%m/%Y", "%Y/%m/%d"];
+const DATETIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+fn read_date(s: &str, fmt: Option<&str>) -> Option<NaiveDate> {
+    match fmt {
+        Some(f) => NaiveDate::parse_from_str(s, f).ok(),
+        None => DATE_FORMATS
+            .iter()
+            .find_map(|f| NaiveDate::parse_from_str(s, f).ok()),
+    }
+}
+
+/// "hh:mm", "hh:mm:ss" or a date and time together
+fn read_time(s: &str) -> Option<(Option<NaiveDateTime>, STime)> {
+    if let Some(dt) = DATETIME_FORMATS
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+    {
+        let t = dt.time();
+        use chrono::Timelike;
+        return Some((Some(dt), STime::new(t.hour() as isize, t.minute() as isize)));
+    }
+    let mut parts = s.split(':');
+    let h = parts.next()?.trim().parse::<isize>().ok()?;
+    let m = parts.next()?.trim().parse::<isize>().ok()?;
+    Some((None, STime::new(h, m)))
+}
+
+/// "hh:mm" or decimal hours
+fn read_duration(s: &str) -> Option<STime> {
+    match read_time(s) {
+        Some((None, t)) => Some(t),
+        _ => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .map(|h| STime::new(0, (h * 60.0).round() as isize)),
+    }
+}
+
+/// How to read the rows
+#[derive(Clone, Debug, Default)]
+pub struct Spec {
+    pub columns: Columns,
+    pub has_head: bool,
+    pub date_format: Option<String>,
+    pub rules: Vec<Rule>,
+}
+
+/// The sessions from the rows, and a message for each row that could not be read
+pub fn to_sessions(s: &str, spec: &Spec) -> (Vec<Session>, Vec<String>) {
+    let rows = read_csv(s);
+    let (head, body, first) = match spec.has_head {
+        true if !rows.is_empty() => (rows[0].clone(), &rows[1..], 2),
+        _ => (Vec::new(), &rows[..], 1),
+    };
+    let mut cols = spec.columns.clone();
+    cols.guess(&head);
+    let ix = |c: &Option<Col>| index(c, &head);
+    let (i_date, i_start, i_end, i_dur) = (
+        ix(&cols.date),
+        ix(&cols.start),
+        ix(&cols.end),
+        ix(&cols.duration),
+    );
+    let (i_job, i_tags, i_note) = (ix(&cols.job), ix(&cols.tags), ix(&cols.note));
+
+    let mut res = Vec::new();
+    let mut errs = Vec::new();
+    for (n, row) in body.iter().enumerate() {
+        let get = |i: Option<usize>| {
+            i.and_then(|i| row.get(i))
+                .map(|f| f.trim())
+                .filter(|f| !f.is_empty())
+        };
+        let mut bad = |m: String| errs.push(format!("Row {}: {}", n + first, m));
+
+        let (start_dt, start) = match get(i_start).map(|v| (v, read_time(v))) {
+            Some((_, Some(t))) => t,
+            Some((v, None)) => {
+                bad(format!("could not read start time \"{}\"", v));
+                continue;
+            }
+            None => {
+                bad("no start time".to_string());
+                continue;
+            }
+        };
+        let date = match (get(i_date), start_dt) {
+            (Some(d), _) => match read_date(d, spec.date_format.as_deref()) {
+                Some(d) => d,
+                None => {
+                    bad(format!("could not read date \"{}\"", d));
+                    continue;
+                }
+            },
+            (None, Some(dt)) => dt.date(),
+            (None, None) => {
+                bad("no date".to_string());
+                continue;
+            }
+        };
+        let end = match (
+            get(i_end).map(|v| (v, read_time(v))),
+            get(i_dur).map(|v| (v, read_duration(v))),
+        ) {
+            (Some((_, Some((Some(dt), _)))), _) => {
+                STime::new(0, (dt - date.and_hms(0, 0, 0)).num_minutes() as isize)
+            }
+            (Some((_, Some((None, t)))), _) if t >= start => t,
+            (Some((_, Some((None, t)))), _) => t + STime::new(24, 0),
+            (_, Some((_, Some(d)))) => start + d,
+            (Some((v, _)), _) | (None, Some((v, _))) => {
+                bad(format!("could not read end \"{}\"", v));
+                continue;
+            }
+            (None, None) => {
+                bad("no end time or duration".to_string());
+                continue;
+            }
+        };
+        if end <= start {
+            bad(format!("does not end after its start {}", start));
+            continue;
+        }
+        let text = get(i_job).unwrap_or("");
+        let job = match spec.rules.is_empty() {
+            true => text.to_string(),
+            false => match spec.rules.iter().find(|r| r.matches(text)) {
+                Some(r) => r.job.clone(),
+                None => {
+                    bad(format!("no rule matched \"{}\"", text));
+                    continue;
+                }
+            },
+        };
+        if job.is_empty() {
+            bad("no job".to_string());
+            continue;
+        }
+        let mut tags: Vec<String> = spec
+            .rules
+            .iter()
+            .find(|r| r.matches(text))
+            .map(|r| r.tags.clone())
+            .unwrap_or_default();
+        tags.extend(
+            get(i_tags)
+                .unwrap_or("")
+                .split([',', ';', ' '])
+                .filter(|t| !t.is_empty())
+                .map(String::from),
+        );
+        res.push((
+            InData {
+                time: start,
+                job,
+                tags,
+                date,
+                line: 0,
+                note: get(i_note).map(String::from),
+            },
+            end,
+        ));
+    }
+    (res, errs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn csv_rows_become_sessions() {
+        let s = "Date,Start,End,Project,Description\n\
+                 2020-01-23,09:00,10:30,client_a,\"Planning, with \"\"Bob\"\"\"\n\
+                 23/01/2020,22:00,01:00,night_shift,\n\
+                 2020-01-24,nine,10:00,client_a,\n\
+                 ,2020-01-25 13:00,2020-01-25 14:15,client_b,\n";
+        let spec = Spec {
+            has_head: true,
+            ..Default::default()
+        };
+        let (sessions, errs) = to_sessions(s, &spec);
+        assert_eq!(sessions.len(), 3);
+        assert_eq!(errs, vec!["Row 4: could not read start time \"nine\""]);
+        assert_eq!(
+            sessions[0].0.note,
+            Some("Planning, with \"Bob\"".to_string())
+        );
+        assert_eq!(sessions[1].1, STime::new(25, 0));
+        assert_eq!(sessions[2].0.date, NaiveDate::from_ymd(2020, 1, 25));
+        assert_eq!(sessions[2].1, STime::new(14, 15));
+
+        let spec = Spec {
+            columns: Columns::parse("date=1,start=2,duration=3,job=4").unwrap(),
+            ..Default::default()
+        };
+        let (sessions, errs) = to_sessions("2020-01-23,9:00,1.5,x\n2020-01-23,9:00,0,x\n", &spec);
+        assert_eq!(sessions[0].1, STime::new(10, 30));
+        assert_eq!(errs, vec!["Row 2: does not end after its start 09:00"]);
+
+        let s = "start,end,job\n\
+                 2020-01-23 10:00,2020-01-23 10:00,a\n\
+                 2020-01-23 10:00,2020-01-22 11:00,a\n\
+                 2020-01-23 10:00,10:00,a\n";
+        let spec = Spec {
+            has_head: true,
+            ..Default::default()
+        };
+        let (sessions, errs) = to_sessions(s, &spec);
+        assert!(sessions.is_empty());
+        assert_eq!(errs.len(), 3);
+        assert_eq!(errs[1], "Row 3: does not end after its start 10:00");
+    }
+}
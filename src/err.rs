@@ -1,3 +1,4 @@
+#![allow(non_local_definitions)] //failure_derive generates these
 use failure_derive::*;
 use gobble::StrungError;
 
@@ -30,6 +31,15 @@ impl TokErr {
     pub fn on_line(self, n: usize) -> LineErr {
         LineErr { line: n, err: self }
     }
+
+    /// The (0 based) line of the first error, where known
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            TokErr::ParseErr(e) => Some(e.line),
+            TokErr::Lines(v) => v.first().map(|e| e.line),
+            _ => None,
+        }
+    }
 }
 
 impl From<&str> for TokErr {
@@ -44,6 +54,12 @@ impl From<String> for TokErr {
     }
 }
 
+impl From<LineErr> for TokErr {
+    fn from(e: LineErr) -> Self {
+        TokErr::Lines(vec![e])
+    }
+}
+
 impl From<std::num::ParseIntError> for TokErr {
     fn from(_: std::num::ParseIntError) -> Self {
         TokErr::ParseIntErr
@@ -0,0 +1,21 @@
+//! Time files shared by the tests
+
+use crate::clockin::{self, AllData, InData, Session};
+
+/// The demo file: two days with implied jobs and clockouts
+pub const DEMO: &str = include_str!("../test_data/demo1.wt");
+
+/// A session over midnight, one of no time, a year end, a leap day,
+/// and a clockin still open at the end
+pub const EDGES: &str = include_str!("../test_data/edges.wt");
+
+/// The file read, with its clocks paired into sessions
+pub fn read(s: &str) -> (AllData, Vec<Session>, Option<InData>) {
+    let data = clockin::read_string(s).unwrap();
+    let (sessions, open) = clockin::pair_sessions(data.clocks.clone()).unwrap();
+    (data, sessions, open)
+}
+
+pub fn sessions(s: &str) -> Vec<Session> {
+    read(s).1
+}
@@ -1,7 +1,12 @@
+use crate::budget::{Budget, Period};
 use crate::clockin::{ClockAction, LineClockAction};
+use crate::group::Member;
 use crate::s_time::STime;
 use gobble::*;
 
+/// Splits a job name into levels, "client/backend/api"
+pub const JOB_SEP: char = '/';
+
 parser! {
     (Date->(usize,usize,Option<isize>))
     (common::UInt,last(ws__('/'),common::UInt),maybe(last(ws__('/'),common::Int)))
@@ -9,7 +14,33 @@ parser! {
 
 parser! {
     (StrVal -> String)
-    or(common::Quoted,common::Ident)
+    or(
+        common::Quoted,
+        string((common::Ident, star((JOB_SEP, common::Ident))))
+    )
+}
+
+/// Notes end at a comma or newline, so those become spaces
+pub fn clean_note(s: &str) -> String {
+    s.replace([',', '\n', '\r'], " ").trim().to_string()
+}
+
+/// Wraps a job or tag in quotes if it would not read back as a plain StrVal
+pub fn do_quotes(s: &str) -> String {
+    match StrVal.parse_s(s) {
+        Ok(ref v) if v == s => s.to_string(),
+        _ => format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
+    }
+}
+
+/// The tag actions that leave exactly these tags set
+pub fn tag_items(tags: &[String]) -> Vec<String> {
+    match tags.split_first() {
+        None => vec!["__".to_string()],
+        Some((f, rest)) => std::iter::once(format!("__{}", do_quotes(f)))
+            .chain(rest.iter().map(|t| format!("_{}", do_quotes(t))))
+            .collect(),
+    }
 }
 
 parser! {
@@ -17,6 +48,11 @@ parser! {
     ('#',Any.except("\n\r,").istar()).ig()
 }
 
+parser! {
+    (Note -> String)
+    ('#', string(Any.except("\n\r,").istar())).map(|(_, s)| s.trim().to_string())
+}
+
 pub fn next_<P: Parser>(p: P) -> impl Parser<Out = P::Out> {
     sep_star(", \t\n\r".istar(), Comment).ig_then(p)
 }
@@ -31,24 +67,57 @@ parser! {
     (common::Int, ":", common::Int).map(|(a, _, b)| STime::new(a, b))
 }
 
+/// Comments are kept here, (as Notes) so they can be attached to a clockin on the same line
 pub fn line_clock_actions() -> impl Parser<Out = Vec<LineClockAction>> {
     star_until_ig(
-        next_((line_col, ClockACTION)).map(|((line, col), action)| LineClockAction {
-            line,
-            col,
-            action,
-        }),
+        ", \t\n\r"
+            .istar()
+            .ig_then((line_col, ClockACTION))
+            .map(|((line, col), action)| LineClockAction { line, col, action }),
         ToEnd,
     )
 }
 
+parser! {
+    (GroupMember->Member)
+    or!(
+        ('$', StrVal).map(|(_, s)| Member::Group(s)),
+        ('_', StrVal).map(|(_, s)| Member::Tag(s)),
+        (StrVal, maybe(JOB_SEP), '*').map(|(s, sep, _)| match sep {
+            Some(_) => Member::Prefix(format!("{}{}", s, JOB_SEP)),
+            None => Member::Prefix(s),
+        }),
+        StrVal.map(Member::Job),
+    )
+}
+
+parser! {
+    (SetBudget->ClockAction)
+    (
+        '+',
+        StrVal,
+        ws__(':'),
+        STIME,
+        maybe(('/', or!("week".asv(Period::Week), "month".asv(Period::Month)))),
+    )
+        .map(|(_, k, _, time, p)| {
+            let period = p.map(|(_, p)| p).unwrap_or(Period::Total);
+            ClockAction::SetBudget(k, Budget { time, period })
+        })
+}
+
+parser! {
+    (Leave->ClockAction)
+    ('!', StrVal, maybe((ws__(':'), STIME))).map(|(_, k, h)| ClockAction::Leave(k, h.map(|(_, t)| t)))
+}
+
 parser! {
     (Group->ClockAction)
     (
         '$',
         StrVal,
         ws__('['),
-        star_until_ig(next_(StrVal), next_("]")),
+        star_until_ig(next_(GroupMember), next_("]")),
     )
         .map(|(_, k, _, v)| ClockAction::DefGroup(k, v))
 }
@@ -56,6 +125,9 @@ parser! {
 parser! {
     (ClockACTION -> ClockAction)
     or!(
+        Note.map(ClockAction::Note),
+        ">>".asv(ClockAction::Push),
+        "<<".asv(ClockAction::Pop),
         //handle tags
         ('_', StrVal).map(|(_, s)| ClockAction::AddTag(s)),
         ("__", maybe(StrVal)).map(|(_, os)| ClockAction::ClearTags(os)),
@@ -68,6 +140,8 @@ parser! {
         }),
         ('=', StrVal, ws__(':'), common::Int).map(|(_, k, _, v)| ClockAction::SetNum(k, v)),
         Group,
+        SetBudget,
+        Leave,
         (StrVal, maybe((ws__('='), common::Int))).map(|(k, set)| match set {
             Some((_, v)) => ClockAction::SetNum(k, v),
             None => ClockAction::SetJob(k),
@@ -87,5 +161,19 @@ pub mod test {
             Ok("hello\tworld".to_string())
         );
         assert!(StrVal.parse_s("_hello").is_err());
+        assert_eq!(
+            StrVal.parse_s("client_a/back/api,"),
+            Ok("client_a/back/api".to_string())
+        );
+        assert_eq!(StrVal.parse_s("client_a/9"), Ok("client_a".to_string()));
+    }
+
+    #[test]
+    pub fn do_quotes_round_trips() {
+        assert_eq!(do_quotes("car_wash"), "car_wash");
+        assert_eq!(do_quotes("dogs ears"), r#""dogs ears""#);
+        for s in &["dogs ears", "say \"hi\"", "back\\slash", "9lives"] {
+            assert_eq!(StrVal.parse_s(&do_quotes(s)), Ok(s.to_string()));
+        }
     }
 }
@@ -0,0 +1,74 @@
+//! Groups of jobs, which can hold other groups, job name prefixes and tags as well as jobs
+
+use crate::clockin::InData;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Member {
+    Job(String),
+    /// Written "$name"
+    Group(String),
+    /// Written "name*", any job starting with name
+    Prefix(String),
+    /// Written "_name", any session with the tag
+    Tag(String),
+}
+
+pub type Groups = BTreeMap<String, Vec<Member>>;
+
+impl Member {
+    fn matches(&self, groups: &Groups, d: &InData, seen: &mut Vec<String>) -> bool {
+        match self {
+            Member::Job(j) => d.job == *j,
+            Member::Prefix(p) => d.job.starts_with(p.as_str()),
+            Member::Tag(t) => d.tags.contains(t),
+            Member::Group(g) => in_group_seen(groups, g, d, seen),
+        }
+    }
+}
+
+fn in_group_seen(groups: &Groups, name: &str, d: &InData, seen: &mut Vec<String>) -> bool {
+    //a group including itself adds nothing
+    if seen.iter().any(|s| s == name) {
+        return false;
+    }
+    seen.push(name.to_string());
+    match groups.get(name) {
+        Some(v) => v.iter().any(|m| m.matches(groups, d, seen)),
+        None => false,
+    }
+}
+
+/// Whether a session belongs to the group, undefined groups hold nothing
+pub fn in_group(groups: &Groups, name: &str, d: &InData) -> bool {
+    in_group_seen(groups, name, d, &mut Vec::new())
+}
+
+/// Every group the session belongs to
+pub fn groups_of(groups: &Groups, d: &InData) -> Vec<String> {
+    groups
+        .keys()
+        .filter(|k| in_group(groups, k, d))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    pub fn groups_nest_and_match_patterns() {
+        let s = "$home[cook,$garden]\n$garden[weed*,_outside,$home]\n$client[client_a/*]\n\
+                 23/01/2019\n  weeding,9:00-10:00\n  _outside,walk,10:00-11:00\n  \
+                 __,client_a/api,11:00-12:00\n  client_ab,12:00-13:00\n";
+        let (data, sessions, _) = fixture::read(s);
+        let g = &data.groups;
+        let names: Vec<Vec<String>> = sessions.iter().map(|s| groups_of(g, &s.0)).collect();
+        assert_eq!(names[0], vec!["garden", "home"]);
+        assert_eq!(names[1], vec!["garden", "home"]);
+        assert_eq!(names[2], vec!["client"]);
+        assert!(names[3].is_empty());
+    }
+}
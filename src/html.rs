@@ -0,0 +1,84 @@
+//! A report as a single html page, with its own styles, ready to send on
+
+use crate::clockin::Session;
+use crate::rate::{self, Rates};
+use crate::report::{duration, html_escape, html_page, summary, Format, Table};
+use crate::s_time::STime;
+use chrono::naive::NaiveDate;
+use std::collections::BTreeMap;
+
+/// Time per job on each day, the date only on the first row of the day
+pub fn daily(sessions: &[Session], rates: &Rates) -> Table {
+    let mut days: BTreeMap<(NaiveDate, &str), STime> = BTreeMap::new();
+    for s in sessions {
+        *days.entry((s.0.date, &s.0.job)).or_default() += duration(s);
+    }
+    let priced = !rates.is_empty();
+    let mut head = vec!["Date", "Job", "Time"];
+    if priced {
+        head.push("Amount");
+    }
+    let mut tab = Table::new(&head);
+    let mut last = None;
+    let (mut time, mut total) = (STime::default(), 0.);
+    for ((d, job), t) in days {
+        let date = match last == Some(d) {
+            true => String::new(),
+            false => d.format("%d/%m/%Y").to_string(),
+        };
+        last = Some(d);
+        let mut row = vec![date, job.to_string(), t.to_string()];
+        if priced {
+            let m = rates.for_job(job).map(|r| rate::amount(t, r));
+            total += m.unwrap_or(0.);
+            row.push(m.map(rate::money).unwrap_or_default());
+        }
+        time += t;
+        tab.rows.push(row);
+    }
+    let mut foot = vec!["Total".to_string(), String::new(), time.to_string()];
+    if priced {
+        foot.push(rate::money(total));
+    }
+    tab.foot.push(foot);
+    tab
+}
+
+pub fn render(sessions: &[Session], rates: &Rates, title: &str) -> String {
+    let range = match (
+        sessions.iter().map(|s| s.0.date).min(),
+        sessions.iter().map(|s| s.0.date).max(),
+    ) {
+        (Some(a), Some(b)) => format!("{} - {}", a.format("%d/%m/%Y"), b.format("%d/%m/%Y")),
+        _ => "No sessions".to_string(),
+    };
+    let body = format!(
+        "<p>{}</p>\n<h2>Summary</h2>\n{}<h2>Daily</h2>\n{}",
+        html_escape(&range),
+        summary(sessions, rates).render(Format::Html),
+        daily(sessions, rates).render(Format::Html),
+    );
+    html_page(title, &body)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    pub fn html_report_prices_jobs() {
+        let sessions = fixture::sessions(fixture::DEMO);
+        let mut rates = Rates::default();
+        rates.add("10").unwrap();
+        let tab = summary(&sessions, &rates);
+        assert_eq!(tab.head.len(), 5);
+        let time: STime = tab.foot[0][2].parse().unwrap();
+        assert_eq!(tab.foot[0][4], rate::money(rate::amount(time, 10.)));
+
+        let page = render(&sessions, &Rates::default(), "Jobs <&>");
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains("<title>Jobs &lt;&amp;&gt;</title>"));
+        assert!(!page.contains("Amount"));
+    }
+}
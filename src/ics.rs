@@ -0,0 +1,334 @@
+//! iCalendar files, each session is a VEVENT
+
+use crate::clockin::{InData, Session};
+use crate::err::TokErr;
+use crate::gob::clean_note;
+use crate::import::Rule;
+use crate::s_time::STime;
+use chrono::naive::{NaiveDate, NaiveDateTime};
+use chrono::offset::{Local, TimeZone, Utc};
+use chrono::Duration;
+
+/// The date and time a number of minutes after the start of a day, (times can pass 24:00)
+pub fn at(d: NaiveDate, t: STime) -> NaiveDateTime {
+    d.and_hms(0, 0, 0) + Duration::minutes(t.as_mins() as i64)
+}
+
+fn stamp(dt: NaiveDateTime) -> String {
+    dt.format("%Y%m%dT%H%M%S").to_string()
+}
+
+/// Escape a TEXT value
+pub fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+pub fn unescape(s: &str) -> String {
+    let mut res = String::new();
+    let mut it = s.chars();
+    while let Some(c) = it.next() {
+        match (c, c == '\\') {
+            (_, true) => match it.next() {
+                Some('n') | Some('N') => res.push('\n'),
+                Some(c) => res.push(c),
+                None => {}
+            },
+            (c, false) => res.push(c),
+        }
+    }
+    res
+}
+
+/// Lines longer than 75 bytes continue on the next line after a space
+pub fn fold(line: &str) -> String {
+    let mut res = String::new();
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            res.push_str("\r\n ");
+            len = 1;
+        }
+        res.push(c);
+        len += c.len_utf8();
+    }
+    res.push_str("\r\n");
+    res
+}
+
+/// The date, and where the session comes among all those started that day.
+/// So it stays the same when the session's time, job or tags are amended,
+/// or lines are added on other days
+pub fn uid(d: &InData, all: &[Session]) -> String {
+    let n = all
+        .iter()
+        .filter(|(a, _)| a.date == d.date && a.line < d.line)
+        .count();
+    format!("{}-{}@work_tock", d.date.format("%Y%m%d"), n + 1)
+}
+
+fn event(s: &Session, uid: String, open: bool, now: NaiveDateTime) -> String {
+    let (d, out) = s;
+    let mut desc = Vec::new();
+    if !d.tags.is_empty() {
+        desc.push(format!("Tags: {}", d.tags.join(", ")));
+    }
+    if let Some(n) = &d.note {
+        desc.push(format!("Note: {}", n));
+    }
+    if open {
+        desc.push("In progress".to_string());
+    }
+    let mut lines = vec![
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}", uid),
+        format!("DTSTAMP:{}", stamp(now)),
+        format!("DTSTART:{}", stamp(at(d.date, d.time))),
+        format!("DTEND:{}", stamp(at(d.date, *out))),
+        format!("SUMMARY:{}", escape(&d.job)),
+    ];
+    if !desc.is_empty() {
+        lines.push(format!("DESCRIPTION:{}", escape(&desc.join("\n"))));
+    }
+    if !d.tags.is_empty() {
+        let cats: Vec<String> = d.tags.iter().map(|t| escape(t)).collect();
+        lines.push(format!("CATEGORIES:{}", cats.join(",")));
+    }
+    if open {
+        lines.push("STATUS:TENTATIVE".to_string());
+    }
+    lines.push("END:VEVENT".to_string());
+    lines.iter().map(|l| fold(l)).collect()
+}
+
+/// A calendar of the sessions.
+/// The open session, if given, is marked in progress up to its current end.
+/// All the sessions in the file are needed to number them through each day
+pub fn export(
+    sessions: &[Session],
+    all: &[Session],
+    open: Option<&InData>,
+    now: NaiveDateTime,
+) -> String {
+    let mut res = fold("BEGIN:VCALENDAR");
+    res.push_str(&fold("VERSION:2.0"));
+    res.push_str(&fold("PRODID:-//work_tock//work_tock//EN"));
+    for s in sessions {
+        res.push_str(&event(s, uid(&s.0, all), Some(&s.0) == open, now));
+    }
+    res.push_str(&fold("END:VCALENDAR"));
+    res
+}
+
+/// Joins folded lines back together
+pub fn unfold(s: &str) -> Vec<String> {
+    let mut res: Vec<String> = Vec::new();
+    for l in s.lines() {
+        let l = l.trim_end_matches('\r');
+        match (
+            l.strip_prefix(' ').or_else(|| l.strip_prefix('\t')),
+            res.last_mut(),
+        ) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => res.push(l.to_string()),
+        }
+    }
+    res
+}
+
+/// The part of an event needed for a session
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub summary: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub categories: Vec<String>,
+}
+
+/// Local time of a DATE-TIME, None for whole day DATE values
+fn read_time(params: &str, v: &str) -> Result<Option<NaiveDateTime>, TokErr> {
+    if params.contains("VALUE=DATE") && !params.contains("VALUE=DATE-TIME") {
+        return Ok(None);
+    }
+    let (v, utc) = match v.strip_suffix('Z') {
+        Some(v) => (v, true),
+        None => (v, false),
+    };
+    let t = NaiveDateTime::parse_from_str(v, "%Y%m%dT%H%M%S")
+        .map_err(|_| TokErr::Mess(format!("Could not read time \"{}\"", v)))?;
+    match utc {
+        true => Ok(Some(
+            Utc.from_utc_datetime(&t)
+                .with_timezone(&Local)
+                .naive_local(),
+        )),
+        false => Ok(Some(t)),
+    }
+}
+
+/// The timed events in a calendar, and the summaries of any left out
+pub fn read_events(s: &str) -> Result<(Vec<Event>, Vec<String>), TokErr> {
+    let mut res = Vec::new();
+    let mut skipped = Vec::new();
+    let mut curr: Option<Vec<(String, String, String)>> = None;
+    for l in unfold(s) {
+        let (head, val) = match l.find(':') {
+            Some(i) => (&l[..i], &l[i + 1..]),
+            None => continue,
+        };
+        let (name, params) = match head.find(';') {
+            Some(i) => (&head[..i], &head[i + 1..]),
+            None => (head, ""),
+        };
+        match (name.to_uppercase().as_ref(), val.to_uppercase().as_ref()) {
+            ("BEGIN", "VEVENT") => curr = Some(Vec::new()),
+            ("END", "VEVENT") => {
+                let props = curr.take().unwrap_or_default();
+                let get = |n: &str| props.iter().find(|p| p.0 == n);
+                let summary = get("SUMMARY").map(|p| unescape(&p.2)).unwrap_or_default();
+                let start = match get("DTSTART") {
+                    Some(p) => read_time(&p.1, &p.2)?,
+                    None => None,
+                };
+                let end = match get("DTEND") {
+                    Some(p) => read_time(&p.1, &p.2)?,
+                    None => None,
+                };
+                match (start, end) {
+                    (Some(start), Some(end)) if end >= start => res.push(Event {
+                        summary,
+                        start,
+                        end,
+                        categories: props
+                            .iter()
+                            .filter(|p| p.0 == "CATEGORIES")
+                            .flat_map(|p| split_list(&p.2))
+                            .collect(),
+                    }),
+                    _ => skipped.push(format!("{} (no start and end time)", summary)),
+                }
+            }
+            _ => {
+                if let Some(c) = curr.as_mut() {
+                    c.push((name.to_uppercase(), params.to_uppercase(), val.to_string()));
+                }
+            }
+        }
+    }
+    Ok((res, skipped))
+}
+
+/// Splits a list value on commas that are not escaped, then unescapes each part
+fn split_list(s: &str) -> Vec<String> {
+    let mut res = vec![String::new()];
+    let mut esc = false;
+    for c in s.chars() {
+        match (c, esc) {
+            (',', false) => res.push(String::new()),
+            _ => {
+                esc = c == '\\' && !esc;
+                if let Some(l) = res.last_mut() {
+                    l.push(c);
+                }
+            }
+        }
+    }
+    res.into_iter()
+        .map(|c| unescape(&c).trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
+/// Sessions for the events matching a rule, with the summary as the note
+/// and categories as tags. The summaries of the rest are returned too
+pub fn to_sessions(events: &[Event], rules: &[Rule]) -> (Vec<Session>, Vec<String>) {
+    let mut res = Vec::new();
+    let mut unmatched = Vec::new();
+    for e in events {
+        let rule = match rules.iter().find(|r| r.matches(&e.summary)) {
+            Some(r) => r,
+            None => {
+                unmatched.push(e.summary.clone());
+                continue;
+            }
+        };
+        let date = e.start.date();
+        let mins =
+            |t: NaiveDateTime| STime::new(0, (t - date.and_hms(0, 0, 0)).num_minutes() as isize);
+        let mut tags = rule.tags.clone();
+        tags.extend(e.categories.iter().map(|c| c.replace(' ', "_")));
+        let note = clean_note(&e.summary);
+        res.push((
+            InData {
+                time: mins(e.start),
+                job: rule.job.clone(),
+                tags,
+                date,
+                line: 0,
+                note: if note.is_empty() { None } else { Some(note) },
+            },
+            mins(e.end),
+        ));
+    }
+    (res, unmatched)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    pub fn export_makes_events() {
+        let s = "23/01/2019\n  _outside,car_wash,12:30-13:50,#soap; water\n  eat,23:00-25:00\n";
+        let sessions = fixture::sessions(s);
+        let now = NaiveDate::from_ymd(2020, 1, 1).and_hms(0, 0, 0);
+        let res = export(&sessions[1..], &sessions, Some(&sessions[1].0), now);
+        assert!(res.contains("UID:20190123-2@work_tock\r\n"));
+        let amended = "22/01/2019\n  walk,9:00-10:00\n#a comment\n\
+                       23/01/2019\n  _outside,car_wash,12:30-13:50\n  sleep,_bed,22:00-25:00\n";
+        let again = fixture::sessions(amended);
+        assert_eq!(uid(&again[2].0, &again), uid(&sessions[1].0, &sessions));
+        let res = export(&sessions, &sessions, Some(&sessions[1].0), now);
+        assert!(res.contains("DESCRIPTION:Tags: outside\\nNote: soap\\; water\r\n"));
+        assert!(res.contains("DTEND:20190124T010000\r\n"));
+        assert_eq!(res.matches("STATUS:TENTATIVE").count(), 1);
+        let back = read_events(&res).unwrap().0;
+        assert_eq!(back[0].summary, "car_wash");
+        assert_eq!(back[0].categories, vec!["outside"]);
+        assert_eq!(
+            fold(&"x".repeat(80)),
+            format!("{}\r\n {}\r\n", "x".repeat(75), "x".repeat(5))
+        );
+    }
+
+    #[test]
+    pub fn import_maps_events() {
+        let s = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nSUMMARY:Daily standup\\, team\r\n\
+                 DTSTART:20200106T093000\r\nDTEND:20200106T094500\r\nCATEGORIES:Work,Team call\r\n\
+                 END:VEVENT\r\nBEGIN:VEVENT\r\nSUMMARY:Lunch\r\nDTSTART:20200106T120000\r\n\
+                 DTEND:20200106T130000\r\nEND:VEVENT\r\nBEGIN:VEVENT\r\nSUMMARY:Holiday\r\n\
+                 DTSTART;VALUE=DATE:20200107\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let (events, skipped) = read_events(s).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(skipped.len(), 1);
+        let rules = crate::import::read_rules("standup => meetings,_internal\n").unwrap();
+        let (sessions, unmatched) = to_sessions(&events, &rules);
+        assert_eq!(unmatched, vec!["Lunch"]);
+        let (d, out) = &sessions[0];
+        assert_eq!(d.job, "meetings");
+        assert_eq!(d.tags, vec!["internal", "Work", "Team_call"]);
+        assert_eq!(d.note, Some("Daily standup  team".to_string()));
+        assert_eq!((d.time, *out), (STime::new(9, 30), STime::new(9, 45)));
+    }
+
+    #[test]
+    pub fn categories_unescape_once() {
+        assert_eq!(
+            split_list(r"a\,b,c\\,d\\n, e\nf"),
+            vec!["a,b", "c\\", "d\\n", "e\nf"]
+        );
+    }
+}
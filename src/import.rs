@@ -0,0 +1,159 @@
+//! Sessions read from other formats, written into a time file in date order
+
+use crate::clockin::{self, Session};
+use crate::err::TokErr;
+use crate::insert;
+use regex::{Regex, RegexBuilder};
+
+/// Maps text matching the pattern to a job, with tags to add
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub pattern: Regex,
+    pub job: String,
+    pub tags: Vec<String>,
+}
+
+impl Rule {
+    pub fn matches(&self, text: &str) -> bool {
+        self.pattern.is_match(text)
+    }
+}
+
+/// One rule per line "pattern => job,_tag,_tag", blank lines and lines starting "#" are ignored.
+/// Patterns are regular expressions, ignoring case
+pub fn read_rules(s: &str) -> Result<Vec<Rule>, TokErr> {
+    let mut res = Vec::new();
+    for (n, l) in s.lines().enumerate() {
+        let l = l.trim();
+        if l.is_empty() || l.starts_with('#') {
+            continue;
+        }
+        let (pattern, rest) = match l.find("=>") {
+            Some(i) => (l[..i].trim(), &l[i + 2..]),
+            None => {
+                return Err(TokErr::from("Rules need a \"=>\" between pattern and job")
+                    .on_line(n)
+                    .into())
+            }
+        };
+        let mut parts = rest.split(',').map(str::trim);
+        let job = parts.next().unwrap_or("").to_string();
+        if job.is_empty() {
+            return Err(TokErr::from("Rule has no job").on_line(n).into());
+        }
+        let pattern = RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| TokErr::Mess(format!("Bad pattern: {}", e)).on_line(n))?;
+        let tags = parts
+            .map(|t| t.trim_start_matches('_').to_string())
+            .filter(|t| !t.is_empty())
+            .collect();
+        res.push(Rule { pattern, job, tags });
+    }
+    Ok(res)
+}
+
+/// Leaves out sessions the file already has, (the same date, times and job),
+/// and repeats within the list. Also gives how many were left out
+pub fn new_only(existing: &str, sessions: Vec<Session>) -> Result<(Vec<Session>, usize), TokErr> {
+    let (old, curr) = clockin::pair_sessions(clockin::read_string(existing)?.clocks)?;
+    let key = |(d, out): &Session| (d.date, d.time, *out, d.job.clone());
+    let mut seen: Vec<_> = old.iter().map(key).collect();
+    if let Some(c) = curr {
+        seen.push((c.date, c.time, c.time, c.job));
+    }
+    let before = sessions.len();
+    let mut res = Vec::new();
+    for s in sessions {
+        let k = key(&s);
+        if !seen.contains(&k) {
+            seen.push(k);
+            res.push(s);
+        }
+    }
+    let dups = before - res.len();
+    Ok((res, dups))
+}
+
+/// The file with the sessions put among the others in date order, as ```add``` would,
+/// a preview of where each one went, and the sessions left out with the reason.
+/// Sessions overlapping each other, or work already in the file, are left out
+pub fn insert_all(existing: &str, sessions: &[Session]) -> (String, String, Vec<String>) {
+    let mut sessions: Vec<&Session> = sessions.iter().collect();
+    sessions.sort_by_key(|(d, _)| (d.date, d.time));
+    let mut res = existing.to_string();
+    let mut preview = Vec::new();
+    let mut skipped = Vec::new();
+    for (d, out) in sessions {
+        match insert::plan_insert(&res, d, *out) {
+            Ok(ins) => {
+                preview.push(ins.preview(&res, 1));
+                res = ins.apply(&res);
+            }
+            Err(e) => skipped.push(format!(
+                "{} {} {}-{} ({})",
+                d.job,
+                d.date.format("%d/%m/%Y"),
+                d.time,
+                out,
+                e
+            )),
+        }
+    }
+    (res, preview.join("\n"), skipped)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+    use crate::s_time::STime;
+
+    #[test]
+    pub fn rules_are_regular_expressions() {
+        let rules =
+            read_rules("#a comment\nstandup => meetings\n^client .: => client_a,_x\n").unwrap();
+        assert!(rules[0].matches("Daily Standup call"));
+        assert!(rules[1].matches("Client A: planning"));
+        assert!(!rules[1].matches("Client AB: planning"));
+        assert_eq!(rules[1].tags, vec!["x"]);
+        assert!(read_rules("standup( => meetings\n").is_err());
+    }
+
+    #[test]
+    pub fn insert_keeps_meaning() {
+        let s = fixture::DEMO;
+        let list = |s: &str| -> Vec<_> {
+            fixture::sessions(s)
+                .into_iter()
+                .map(|(d, o)| (d.job, d.tags, d.date, d.time, o))
+                .collect()
+        };
+        let before = fixture::sessions(s);
+        let new = fixture::sessions("20/01/2019\n  \"team meeting\",_work,9:00-10:30,#plan\n");
+        let (res, _, skipped) = insert_all(s, &new);
+        assert!(skipped.is_empty());
+        let after = fixture::sessions(&res);
+        assert_eq!(after.len(), before.len() + 1);
+        //the earliest date goes first
+        let (d, out) = &after[0];
+        assert_eq!(d.job, "team meeting");
+        assert_eq!(d.note, Some("plan".to_string()));
+        assert_eq!(*out, STime::new(10, 30));
+        assert_eq!(list(&res)[1..], list(s)[..]);
+
+        let again = vec![new[0].clone(), new[0].clone(), before[0].clone()];
+        let (kept, dups) = new_only(s, again).unwrap();
+        assert_eq!((kept.len(), dups), (1, 2));
+
+        let more = fixture::sessions(
+            "24/01/2019\n  meet,12:30-13:30\n20/01/2019\n  a,9:00-10:00\n  b,9:30-11:00\n",
+        );
+        let (res, _, skipped) = insert_all(s, &more);
+        assert_eq!(fixture::sessions(&res).len(), before.len() + 1);
+        assert_eq!(skipped.len(), 2);
+        assert!(skipped[0].starts_with("b 20/01/2019 09:30-11:00 ("));
+        assert!(skipped[1].starts_with("meet 24/01/2019 12:30-13:30 ("));
+    }
+}
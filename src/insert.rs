@@ -0,0 +1,297 @@
+//! Placing whole sessions into the middle of a time file,
+//! rather than on the end as the clock in/out commands do.
+
+use crate::clockin::{self, ClockAction, DateWalker, InData, LineClockAction};
+use crate::err::TokErr;
+use crate::gob::{self, clean_note, do_quotes, tag_items};
+use crate::s_time::STime;
+use chrono::naive::NaiveDate;
+use gobble::Parser;
+
+/// Lines to be placed before the given (0 based) line of a file
+#[derive(Debug, PartialEq)]
+pub struct Insertion {
+    pub line: usize,
+    pub lines: Vec<String>,
+}
+
+impl Insertion {
+    /// The file contents with the new lines in place
+    pub fn apply(&self, s: &str) -> String {
+        let mut res = String::new();
+        let mut done = false;
+        for (i, l) in s.split_inclusive('\n').enumerate() {
+            if i == self.line {
+                self.push_lines(&mut res);
+                done = true;
+            }
+            res.push_str(l);
+        }
+        if !done {
+            if !res.is_empty() && !res.ends_with('\n') {
+                res.push('\n');
+            }
+            self.push_lines(&mut res);
+        }
+        res
+    }
+
+    fn push_lines(&self, res: &mut String) {
+        for l in &self.lines {
+            res.push_str(l);
+            res.push('\n');
+        }
+    }
+
+    /// A diff style view of the insertion with a little surrounding context
+    pub fn preview(&self, s: &str, context: usize) -> String {
+        let lines: Vec<&str> = s.lines().collect();
+        let start = self.line.saturating_sub(context);
+        let fin = (self.line + context).min(lines.len());
+        let mut res = String::new();
+        for (i, l) in lines
+            .iter()
+            .enumerate()
+            .take(self.line.min(lines.len()))
+            .skip(start)
+        {
+            res.push_str(&format!("  {:>5} | {}\n", i + 1, l));
+        }
+        for l in &self.lines {
+            res.push_str(&format!("+       | {}\n", l));
+        }
+        for (i, l) in lines.iter().enumerate().take(fin).skip(self.line) {
+            res.push_str(&format!("  {:>5} | {}\n", i + 1, l));
+        }
+        res
+    }
+}
+
+/// The job and tags in force at a point in the file
+#[derive(Clone, Debug)]
+struct WalkState {
+    dates: DateWalker,
+    job: String,
+    tags: Vec<String>,
+    open: Option<usize>, //line of a clockin not yet ended
+}
+
+impl WalkState {
+    fn new() -> Self {
+        WalkState {
+            dates: DateWalker::default(),
+            job: "General".to_string(),
+            tags: Vec::new(),
+            open: None,
+        }
+    }
+
+    fn apply(&mut self, ac: &LineClockAction) -> Result<(), TokErr> {
+        use ClockAction::*;
+        self.dates.step(ac)?;
+        match &ac.action {
+            SetJob(j) => self.job = j.clone(),
+            SetDate(_, _, _) | SetNum(_, _) => {}
+            AddTag(s) => self.tags.push(s.clone()),
+            ClearTags(Some(s)) => self.tags = vec![s.clone()],
+            ClearTags(None) => self.tags.clear(),
+            In(_) => self.open = Some(ac.line),
+            Out(_) | InOut(_, _) => self.open = None,
+            DefGroup(_, _) | SetBudget(_, _) | Leave(_, _) | Note(_) | Push | Pop => {}
+        }
+        Ok(())
+    }
+}
+
+/// The latest time mentioned by a clock action
+fn action_time(ac: &ClockAction) -> Option<STime> {
+    match ac {
+        ClockAction::In(t) | ClockAction::Out(t) | ClockAction::InOut(_, t) => Some(*t),
+        _ => None,
+    }
+}
+
+/// minutes since the start of the common era, so sessions over midnight compare properly
+fn abs_mins(d: NaiveDate, t: STime) -> i64 {
+    (d - NaiveDate::from_ymd(1, 1, 1)).num_minutes() + t.as_mins() as i64
+}
+
+/// Work out where a complete session belongs in the file, and the lines needed to put it there.
+/// The job and tags in force after the new lines are restored, so no other entry changes meaning.
+pub fn plan_insert(s: &str, idat: &InData, out: STime) -> Result<Insertion, TokErr> {
+    if out <= idat.time {
+        return Err(TokErr::NegativeTime);
+    }
+    let actions = gob::line_clock_actions()
+        .parse_s(s)
+        .map_err(|e| e.strung())?;
+
+    //refuse anything overlapping work already recorded
+    let (sessions, curr) = clockin::pair_sessions(clockin::read_string(s)?.clocks)?;
+    let (n_st, n_fin) = (abs_mins(idat.date, idat.time), abs_mins(idat.date, out));
+    for (d, o) in &sessions {
+        if abs_mins(d.date, d.time) < n_fin && n_st < abs_mins(d.date, *o) {
+            return Err(TokErr::Mess(format!(
+                "New session overlaps {} ({}-{}) on line {}",
+                d.job,
+                d.time,
+                o,
+                d.line + 1
+            )));
+        }
+    }
+    if let Some(c) = curr {
+        if abs_mins(c.date, c.time) < n_fin {
+            return Err(TokErr::Mess(format!(
+                "New session overlaps {}, which is still clocked in",
+                c
+            )));
+        }
+    }
+
+    //state after each action
+    let mut states = Vec::with_capacity(actions.len());
+    let mut st = WalkState::new();
+    for ac in &actions {
+        st.apply(ac)?;
+        states.push(st.clone());
+    }
+
+    let sections: Vec<(usize, NaiveDate)> = actions
+        .iter()
+        .enumerate()
+        .filter(|(_, ac)| matches!(ac.action, ClockAction::SetDate(..)))
+        .filter_map(|(i, _)| states[i].dates.date.map(|d| (i, d)))
+        .collect();
+
+    let n_lines = s.split_inclusive('\n').count();
+    let (after, line, header) = match sections.iter().rposition(|(_, d)| *d == idat.date) {
+        Some(sp) => {
+            let (s_start, _) = sections[sp];
+            let s_end = sections.get(sp + 1).map(|x| x.0).unwrap_or(actions.len());
+            let k = (s_start..s_end)
+                .rev()
+                .find(|&i| action_time(&actions[i].action).map(|t| t <= idat.time) == Some(true))
+                .unwrap_or(s_start);
+            let ln = actions[k].line;
+            let m = (k..actions.len())
+                .take_while(|&i| actions[i].line == ln)
+                .last()
+                .unwrap_or(k);
+            if (k + 1..=m).any(|i| action_time(&actions[i].action).is_some()) {
+                return Err(TokErr::Mess(format!(
+                    "The new session belongs in the middle of line {}, please split that line first",
+                    ln + 1
+                )));
+            }
+            (Some(m), ln + 1, false)
+        }
+        None => match sections.iter().find(|(_, d)| *d > idat.date) {
+            Some(&(j, _)) => {
+                if j > 0 && actions[j - 1].line == actions[j].line {
+                    return Err(TokErr::Mess(format!(
+                        "The new session belongs in the middle of line {}, please split that line first",
+                        actions[j].line + 1
+                    )));
+                }
+                (j.checked_sub(1), actions[j].line, true)
+            }
+            None => (actions.len().checked_sub(1), n_lines, true),
+        },
+    };
+    let at_end = line >= n_lines;
+
+    let prev = after
+        .map(|i| states[i].clone())
+        .unwrap_or_else(WalkState::new);
+    if let Some(l) = prev.open {
+        return Err(TokErr::Mess(format!(
+            "The clockin on line {} is not clocked out, adding a session after it would end it",
+            l + 1
+        )));
+    }
+
+    let mut lines = Vec::new();
+    if header {
+        lines.push(idat.date.format("%d/%m/%Y").to_string());
+    }
+    let mut items = Vec::new();
+    if idat.job != prev.job {
+        items.push(do_quotes(&idat.job));
+    }
+    if idat.tags != prev.tags {
+        items.extend(tag_items(&idat.tags));
+    }
+    items.push(format!("{}-{}", idat.time, out));
+    if let Some(n) = &idat.note {
+        items.push(format!("#{}", clean_note(n)));
+    }
+    lines.push(format!("\t{}", items.join(",")));
+
+    //put things back how they were for the lines that follow
+    let mut restore = Vec::new();
+    if idat.job != prev.job && !at_end {
+        restore.push(do_quotes(&prev.job));
+    }
+    if idat.tags != prev.tags {
+        restore.extend(tag_items(&prev.tags));
+    }
+    if !restore.is_empty() {
+        lines.push(format!("\t{}", restore.join(",")));
+    }
+
+    Ok(Insertion { line, lines })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    fn s_list(s: &str) -> Vec<(String, Vec<String>, NaiveDate, STime, STime)> {
+        fixture::sessions(s)
+            .into_iter()
+            .map(|(d, o)| (d.job, d.tags, d.date, d.time, o))
+            .collect()
+    }
+
+    fn in_data(job: &str, tags: &[&str], d: u32, time: STime) -> InData {
+        InData {
+            time,
+            date: NaiveDate::from_ymd(2019, 1, d),
+            job: job.to_string(),
+            tags: tags.iter().map(|s| s.to_string()).collect(),
+            line: 0,
+            note: None,
+        }
+    }
+
+    #[test]
+    pub fn insert_keeps_file_meaning() {
+        let s = fixture::DEMO;
+        let idat = in_data("fix", &["urgent"], 23, STime::new(18, 30));
+        let ins = plan_insert(s, &idat, STime::new(19, 0)).unwrap();
+        assert_eq!(ins.line, 7);
+
+        let mut after = s_list(&ins.apply(s));
+        let pos = after.iter().position(|x| x.0 == "fix").unwrap();
+        let added = after.remove(pos);
+        assert_eq!(added.1, vec!["urgent".to_string()]);
+        assert_eq!(after, s_list(s));
+
+        let idat = in_data("eat", &[], 20, STime::new(9, 0));
+        let ins = plan_insert(s, &idat, STime::new(10, 0)).unwrap();
+        assert_eq!(ins.line, 1);
+        assert_eq!(
+            ins.lines,
+            vec!["20/01/2019", "\teat,09:00-10:00", "\tGeneral"]
+        );
+    }
+
+    #[test]
+    pub fn insert_refuses_overlap() {
+        let s = fixture::DEMO;
+        let idat = in_data("eat", &[], 24, STime::new(12, 30));
+        assert!(plan_insert(s, &idat, STime::new(13, 30)).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! A record of what each run appended to the file, so the last one can be taken back out.
+//!
+//! Each line of the journal is "start\tend\ttext" with the text escaped onto one line.
+
+use crate::err::TokErr;
+
+/// Older entries are dropped past this many
+pub const MAX_ENTRIES: usize = 20;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+impl Entry {
+    /// Everything in the file from start onwards
+    pub fn since(s: &str, start: usize) -> Self {
+        Entry {
+            start,
+            end: s.len(),
+            text: s[start..].to_string(),
+        }
+    }
+}
+
+pub fn journal_path(fname: &str) -> String {
+    format!("{}.journal", fname)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+        .replace('\r', "\\r")
+}
+
+fn unescape(s: &str) -> String {
+    let mut res = String::new();
+    let mut it = s.chars();
+    while let Some(c) = it.next() {
+        match c {
+            '\\' => match it.next() {
+                Some('n') => res.push('\n'),
+                Some('t') => res.push('\t'),
+                Some('r') => res.push('\r'),
+                Some(c) => res.push(c),
+                None => {}
+            },
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+pub fn read_entries(s: &str) -> Result<Vec<Entry>, TokErr> {
+    let mut res = Vec::new();
+    for (n, l) in s.lines().enumerate().filter(|(_, l)| !l.is_empty()) {
+        let mut parts = l.splitn(3, '\t');
+        let mut num = || -> Result<usize, TokErr> {
+            Ok(parts
+                .next()
+                .ok_or_else(|| TokErr::from("Journal entry too short").on_line(n))?
+                .parse()?)
+        };
+        let (start, end) = (num()?, num()?);
+        let text = unescape(parts.next().unwrap_or(""));
+        res.push(Entry { start, end, text });
+    }
+    Ok(res)
+}
+
+pub fn write_entries(v: &[Entry]) -> String {
+    v.iter()
+        .map(|e| format!("{}\t{}\t{}\n", e.start, e.end, escape(&e.text)))
+        .collect()
+}
+
+/// Add an entry, or replace the last one if it was started by the same run
+pub fn record(v: &mut Vec<Entry>, e: Entry) {
+    let same_run = match v.last() {
+        Some(l) => l.start == e.start && e.text.starts_with(&l.text),
+        None => false,
+    };
+    if same_run {
+        v.pop();
+    }
+    v.push(e);
+    if v.len() > MAX_ENTRIES {
+        v.drain(..v.len() - MAX_ENTRIES);
+    }
+}
+
+/// The file without the entry's text.
+/// The text must still be the end of the file, so nothing written since can be lost
+pub fn remove_entry(s: &str, e: &Entry) -> Result<String, TokErr> {
+    if s.get(e.start..e.end) == Some(&e.text) {
+        if e.end != s.len() {
+            return Err(TokErr::from(
+                "The file has been added to since, please edit it by hand",
+            ));
+        }
+        return Ok(s[..e.start].to_string());
+    }
+    Err(TokErr::from(
+        "The last append is no longer at the end of the file, please edit it by hand",
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    pub fn journal_round_trips_and_undoes() {
+        let before = "23/01/2019\n\tcar_wash,12:30\n";
+        let after = format!("{}  -13:00\n\tprogramming,\\\"x\",14:00\n", before);
+        let mut v = vec![Entry::since(&after, before.len() - 8)];
+        record(&mut v, Entry::since(&after, before.len()));
+        let v = read_entries(&write_entries(&v)).unwrap();
+        assert_eq!(v.len(), 2);
+        assert_eq!(remove_entry(&after, &v[1]).unwrap(), before);
+        assert!(remove_entry(&format!("{}\t14:30\n", after), &v[1]).is_err());
+        // The same text somewhere else is not enough
+        assert!(remove_entry(&format!("\n{}", after), &v[1]).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+//! Days away from work, written "!holiday" or "!sick:4:00" after a date
+
+use crate::report::{Format, Table};
+use crate::s_time::STime;
+use chrono::naive::NaiveDate;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Leave {
+    pub date: NaiveDate,
+    pub kind: String,
+    /// Hours credited, None means the whole day's target
+    pub hours: Option<STime>,
+    pub line: usize,
+}
+
+/// Hours credited on a date, given the target for that day.
+/// A whole day off covers the day, however many entries there are
+pub fn credit(leave: &[Leave], date: NaiveDate, target: STime) -> STime {
+    let mut res = STime::new(0, 0);
+    for l in leave.iter().filter(|l| l.date == date) {
+        match l.hours {
+            Some(h) => res += h,
+            None => return target,
+        }
+    }
+    res
+}
+
+fn hours(h: Option<STime>) -> String {
+    h.map(|t| t.to_string())
+        .unwrap_or_else(|| "day".to_string())
+}
+
+/// Days and set hours of each kind of leave
+pub fn summary(leave: &[Leave]) -> BTreeMap<String, (usize, STime)> {
+    let mut res: BTreeMap<String, (usize, STime)> = BTreeMap::new();
+    for l in leave {
+        let e = res.entry(l.kind.clone()).or_default();
+        e.0 += 1;
+        e.1 += l.hours.unwrap_or_default();
+    }
+    res
+}
+
+/// Every entry, then the totals by kind
+pub fn render(leave: &[Leave], f: Format) -> String {
+    let mut list = Table::new(&["Date", "Leave", "Hours"]);
+    for l in leave {
+        list.rows.push(vec![
+            l.date.format("%d/%m/%Y").to_string(),
+            l.kind.clone(),
+            hours(l.hours),
+        ]);
+    }
+    let mut kinds = Table::new(&["Leave", "Days", "Set Hours"]);
+    for (k, (n, t)) in summary(leave) {
+        kinds.rows.push(vec![k, n.to_string(), t.to_string()]);
+    }
+    match f {
+        Format::Csv => format!("{}\n{}", list.csv(), kinds.csv()),
+        _ => format!("{}\n{}", list.render(f), kinds.render(f)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clockin;
+
+    #[test]
+    pub fn leave_read_after_dates() {
+        let s = "21/01/2019\n  !holiday\n22/01/2019\n  !sick:3:00,work,13:00-17:00\n";
+        let data = clockin::read_string(s).unwrap();
+        assert_eq!(data.leave.len(), 2);
+        assert_eq!(data.leave[1].date, NaiveDate::from_ymd(2019, 1, 22));
+        let t = STime::new(7, 0);
+        assert_eq!(credit(&data.leave, NaiveDate::from_ymd(2019, 1, 21), t), t);
+        assert_eq!(
+            credit(&data.leave, NaiveDate::from_ymd(2019, 1, 22), t),
+            STime::new(3, 0)
+        );
+        assert_eq!(summary(&data.leave)["sick"], (1, STime::new(3, 0)));
+    }
+
+    #[test]
+    pub fn leave_covers_a_day_once() {
+        let s = "21/01/2019\n  !holiday\n  !sick\n  !sick:3:00\n";
+        let data = clockin::read_string(s).unwrap();
+        let t = STime::new(7, 0);
+        assert_eq!(credit(&data.leave, NaiveDate::from_ymd(2019, 1, 21), t), t);
+        assert!(clockin::read_string("!holiday\n21/01/2019\n").is_err());
+    }
+}
@@ -4,6 +4,33 @@ pub use crate::clockin::{ClockAction, Clockin, InData, LineClockAction};
 pub mod s_time;
 pub use crate::s_time::STime;
 pub mod gob;
+pub mod insert;
+pub mod punch;
+pub mod amend;
+pub mod journal;
+pub mod report;
+pub mod timesheet;
+pub mod breakdown;
+pub mod tree;
+pub mod group;
+pub mod budget;
+pub mod balance;
+pub mod leave;
+pub mod rate;
+pub mod chart;
+pub mod calendar;
+pub mod html;
+pub mod markdown;
+pub mod serve;
+pub mod template;
+pub mod ics;
+pub mod import;
+pub mod csv_import;
+pub mod timeclock;
+pub mod term;
+pub mod tui;
+#[cfg(test)]
+mod fixture;
 //mod pesto;
 //pub use pesto::{Pestable, Rule};
 pub mod err;
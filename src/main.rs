@@ -23,7 +23,7 @@
 //!       work_tock --job_s dothing -p --since 03/04/2020
 //!
 //!
-//!   The program works with a single text file that is easy to edit if needed. The program will never overwrite your file, only read and append, so all data in that file will otherwise remain untouched.
+//!   The program works with a single text file that is easy to edit if needed. The program will only ever append to your file, except for commands like ```add```, ```amend```, ```import``` and ```undo``` that say otherwise, and these keep a backup first.
 //!
 //!   To set the location of the core file, the default config for your program can be found in "$HOME/.config/work\_tock/init.toml 
 //!
@@ -37,6 +37,15 @@
 //!       snake=true  
 //!       
 //!       #camel=true  #if you prefer camelCase
+//!
+//!       #Optional editor for "work_tock edit", otherwise $EDITOR is used
+//!       #editor="vim"
+//!
+//!       #Optional working hours for "work_tock balance"
+//!       #target_day="7:30"
+//!       #target_week="37:30"  #spread over Monday to Friday
+//!       #target_fri="4:00"  #any of target_mon..target_sun
+//!       #holidays="25/12/2020,26/12/2020"
 //!   ```
 //!
 //!   A standard file looks like this :
@@ -64,7 +73,11 @@
 //!   * Clockouts are  "-hh:mm"
 //!   * Tags begin with an "\_" and can be cleared with "\_\_"
 //!   * Dates are dd/mm/yyyy, but if you set year=2019, dates can be dd/mm after that.
-//!   * Groups are defined by a ```$group_name[list,of,jobs,in,group]```
+//!   * Groups are defined by a ```$group_name[list,of,jobs,in,group]```, members can also be other groups ```$other```, job prefixes ```client_a/*``` or tags ```_urgent```
+//!   * Budgets are set with ```+job_or_group:40:00```, optionally followed by ```/week``` or ```/month```
+//!   * Leave is marked after a date with ```!holiday``` or ```!sick:3:30``` for part of a day
+//!   * Comments begin with "#" and a comment on the same line as a clockin becomes its note
+//!   * ">>" marks a clockin as interrupting the current job, and "<<" marks one as going back to it
 //!
 //!   Every Clockin will use the most recent Job,Date, and Tags for the clocking, 
 //!
@@ -119,15 +132,273 @@
 //!
 //!
 //!
+//!   Adding Forgotten Sessions
+//!   ------------------
+//!
+//!   If you forgot to clock in for something, ```add``` will put a complete session into the file in date order, rather than on the end.
+//!
+//!       work_tock add car_wash 09:00 10:30 -d 23/01/2019 -t outside
+//!
+//!   It shows where the lines will go and asks before writing. Any job or tag changes are undone straight after the new session so the rest of the file keeps its meaning. Sessions that overlap existing work are refused. The file is backed up to "<file>.bak" before it is rewritten.
+//!
+//!   Amending the Last Entry
+//!   ------------------
+//!
+//!   If you clocked in to the wrong job, or at the wrong time, ```amend``` changes the most recent clockin in place.
+//!
+//!       work_tock amend --job car_wash --at 12:30 -t outside -n "round the back"
+//!
+//!   * --job : the job
+//!   * --at : the start time
+//!   * -t / --clear\_tags : the tags
+//!   * -n : the note, which is a comment on the same line as the clockin
+//!   * --end : the end time of the most recent session, if it has been clocked out
+//!
+//!   The changed lines are shown, and the file is backed up to "<file>.bak" before it is rewritten.
+//!
+//!   Undo
+//!   ------------------
+//!
+//!   Every run of -i, -o or -q records what it appended in "<file>.journal", and
+//!
+//!       work_tock undo
+//!
+//!   removes exactly those lines again, going back one run each time it is called. It will only remove text that is still the end of the file, so anything written by hand since is safe, and the file is backed up to "<file>.bak" first.
+//!
+//!   Editing by Hand
+//!   ------------------
+//!
+//!       work_tock edit --today
+//!
+//!   opens the file in your editor ("editor" in the config, otherwise $EDITOR), starting at today's entries, or at a line given with "-l". When the editor closes the file is read again, and if there is a mistake the line is shown and you can go straight back to fix it.
+//!
+//!   Interruptions
+//!   ------------------
+//!
+//!   When something urgent comes up, ```push``` clocks in to it and remembers what it interrupted, and ```pop``` goes back to the interrupted job with exactly the tags it had.
+//!
+//!       work_tock push fire_fighting -t urgent
+//!       work_tock pop
+//!
+//!   Pushes can be nested. There is no hidden state, the file records them as ">>" in front of the interrupting clockin and "<<" in front of the resuming one:
+//!
+//!   ```toml
+//!   25/01/2019
+//!       meeting,_client_a,09:00
+//!       >>,fire_fighting,__urgent,10:00
+//!       <<,meeting,__client_a,10:45
+//!   ```
+//!
+//!   Timesheets
+//!   ------------------
+//!
+//!       work_tock --since 01/04/2020 --until 30/04/2020 timesheet
+//!
+//!   prints a grid for each week in the filtered range, with jobs as rows, Monday to Sunday as columns, and totals for both. Any of the filters above can be used. Add ```--format csv```, ```--format md``` or ```--format html``` for CSV, Markdown or html instead of aligned text. (A default format can also be set with "format" in the config)
+//!
+//!   Breakdowns
+//!   ------------------
+//!
+//!       work_tock -m --by week,job
+//!
+//!   prints nested subtotals, here each week of the month with the jobs done in it under it. Any of day, week, month, year, job, tag or group can be given, in any order, separated by commas. Each line shows the total time, the number of sessions, and the average session length. Sessions with several tags (or jobs in several groups) are counted under each of them, so those subtotals may add up to more than the total. ```--format``` works here too.
+//!
+//!   Tags
+//!   ------------------
+//!
+//!       work_tock -m tags
+//!
+//!   prints the time spent under each tag, then each tag within each job. Sessions without tags are totalled as "(untagged)".
+//!
+//!   ```--tag``` can be given more than once, and then only sessions with all of the tags are kept. Add ```--any_tag``` to keep sessions with any of them instead. ```--untagged``` keeps only sessions with no tags at all. ```--by tag``` and ```--by job,tag``` give the same totals alongside other breakdowns.
+//!
+//!   Job Trees
+//!   ------------------
+//!
+//!   Job names can use "/" to make a hierarchy, eg ```client_a/backend/api```, and need no quotes. Each level should start with a letter.
+//!
+//!       work_tock -m tree
+//!
+//!   prints every level indented under its parent, with "Total" including everything below it and "Own" only the time booked to that exact job. ```--under client_a/backend``` filters to that job and everything under it, and works with any report.
+//!
+//!   Groups
+//!   ------------------
+//!
+//!   A group can hold more than job names:
+//!
+//!       $client_a[client_a/*]
+//!       $office[$client_a,meetings,_billable]
+//!
+//!   Here "client_a" holds every job starting with "client_a/", and "office" holds everything in "client_a", the "meetings" job, and any session tagged "billable". A job can be in several groups.
+//!
+//!       work_tock -m groups
+//!
+//!   prints the time in each group, and each job within it. ```-g``` can be given more than once to keep sessions in any of the groups, eg ```-g office -g home_jobs```.
+//!
+//!   Budgets
+//!   ------------------
+//!
+//!   A budget sets aside hours for a job or group:
+//!
+//!       +client_a:40:00
+//!       +client_a/support:5:00/week
+//!       $office[meetings,admin]
+//!       +office:20:00/month
+//!
+//!   A budget named after a group covers everything in the group. Otherwise it covers the job and every job under it. Without "/week" or "/month" it counts all time in the file.
+//!
+//!   Clocking in to a job under a budget shows how much of it is used, with a warning if it is already over.
+//!
+//!       work_tock budget
+//!
+//!   prints every budget with the time used in its current week or month and what is left. The filters do not change what counts against a budget.
+//!
+//!   Working Hours Balance
+//!   ------------------
+//!
+//!   With a target set in the config (see above)
+//!
+//!       work_tock --since 01/01/2020 balance
+//!
+//!   prints the target and the time actually worked for each day, the difference, and the running flextime balance. Add ```-w``` for one row per week. Days listed in "holidays" expect no hours. The range runs from ```--since``` (or the first session) to ```--until``` (or today).
+//!
+//!   Leave
+//!   ------------------
+//!
+//!   Holidays, sick days and other leave go after the date they were taken:
+//!
+//!       22/01/2020
+//!         !holiday
+//!       23/01/2020
+//!         !sick:3:00,work,13:00-17:00
+//!
+//!   On its own a marker covers the whole day, so the day's target counts as met in the balance, however many markers the day has. With a time it credits just those hours. A marker must come after a date. Leave is never counted as a job. The default report lists it under the totals, and
+//!
+//!       work_tock -m leave
+//!
+//!   lists every entry and the days of each kind. The date filters apply to leave too.
+//!
+//!   Charts
+//!   ------------------
+//!
+//!       work_tock -m chart
+//!
+//!   draws a bar for the time on each job, then a bar for each day in the range, and a one line sparkline of the days. Use ```--by tag``` or ```--by group``` (after "chart") to chart those instead of jobs. Bars fit the width of the terminal (or $COLUMNS when that cannot be found, or 80), unless ```--width``` is given.
+//!
+//!   Calendar
+//!   ------------------
+//!
+//!       work_tock --job car_wash calendar
+//!
+//!   prints a calendar for each month with sessions, shading each day by how much was worked compared to the busiest day. ```--hours``` (after "calendar") shows whole hours instead, and ```-y``` gives a grid for each year with a column per week. Any filter can be used, eg to see one job or group.
+//!
+//!   Exporting
+//!   ------------------
+//!
+//!       work_tock -m export ics -o work.ics
+//!
+//!   writes the filtered sessions as an iCalendar file (stdout without ```-o```). Each session becomes an event with the job as its title, and tags and notes in the description. The event id comes from the date and the place of the session within that day, so after amending a session's time or job, re-importing an updated export replaces its event instead of copying it. The open session is left out unless ```--open``` is given, then it runs up to now and is marked tentative. Messages such as "You have been clocked in..." go to stderr, so only the export is written to stdout, as with ```--format csv``` and the other formats.
+//!
+//!   Importing Calendars
+//!   ------------------
+//!
+//!       work_tock import ics meetings.ics --dry_run
+//!
+//!   reads the events from an iCalendar file and shows where their lines would go, without ```--dry_run``` it writes them. Which events are wanted, and their jobs, come from a rules file (```--rules``` or "import_rules" in the config, default "{HOME}/.config/work_tock/import_rules"), with one rule per line:
+//!
+//!       #pattern => job,_tag,_tag
+//!       standup => meetings,_internal
+//!       ^Client A: => client_a/meetings
+//!
+//!   Patterns are regular expressions, found anywhere in the event title and ignoring case, so use "^" and "$" to match the whole title. The first rule to match is used, and events matching none are listed as skipped, as are all day events. Event categories become tags, and the title becomes the note. Sessions are put in date order among the others, as ```add``` does, and any job or tag changes are undone straight after each one. Sessions that overlap existing work, or each other, are listed as skipped with the reason, and the rest are still added. The file is backed up to "<file>.bak" before it is rewritten.
+//!
+//!   Importing CSV
+//!   ------------------
+//!
+//!       work_tock import csv toggl_export.csv --dry_run
+//!
+//!   adds a session for each row of a CSV file. Columns are found from the header row by names such as "Date", "Start", "End", "Duration", "Project", "Tags" and "Description", or can be given with ```--columns date=Day,start=From,end=To,job=Project,note=Description```. Numbers pick columns by position, and ```--no_header``` says the first row is data. Dates are read as 2020-01-23 or 23/01/2020 unless ```--date_format``` is given. Start and end can be times or dates with times, and a duration (hh:mm or decimal hours) can stand in for the end. With ```--rules``` the project is mapped to a job as for calendars.
+//!
+//!   Rows that could not be read, or that do not end after they start, are listed, and sessions already in the file (same date, times and job) are skipped, so running an import twice adds nothing the second time. This applies to calendar imports too.
+//!
+//!   Timeclock
+//!   ------------------
+//!
+//!       work_tock --since 01/01/2020 export timeclock -o work.timeclock
+//!       work_tock import timeclock work.timeclock
+//!
+//!   write and read the timeclock format used by ledger and hledger, with an "i" and "o" line for each session. Jobs are the accounts, with "/" becoming ":" so job trees become account trees. Notes are the payee, and tags go in a "; tags: a, b" comment before the clock in. With ```--open``` the open session is written as a clock in with no clock out.
+//!
+//!   HTML Reports
+//!   ------------------
+//!
+//!       work_tock --month 4 --group client_a --format html --rates 65 > report.html
+//!
+//!   writes the filtered sessions as a single html page with its own styles, ready to send to a client. It has a summary table of the sessions and time for each job, then the time for each job on each day, with totals. Rates are optional: a bare number is the hourly rate for every job, and "job=rate" sets it for a job and the jobs under it, eg: ```--rates 50,client_a=80,client_a/support=60```. With rates the tables gain Rate and Amount columns and a total amount. Rates can also be kept in the config as ```rates="50,client_a=80"```. The other reports also take ```--format html```, giving their tables as html.
+//!
+//!   Markdown Reports
+//!   ------------------
+//!
+//!       work_tock -w --format md
+//!
+//!   prints the filtered sessions as Markdown, ready to paste into a wiki or a PR description. Each day gets a heading and a table of job, start, end, duration and note, as ```-p``` prints them, with the day's total. A summary table of the sessions and time for each job follows. Without a report subcommand, ```--format csv``` gives just that summary table as CSV, with the rate and amount when ```--rates``` are set.
+//!
+//!   Templates
+//!   ------------------
+//!
+//!       work_tock -w --template weekly_email
+//!
+//!   renders the filtered sessions with a template of your own, kept in "~/.config/work_tock/templates" (or the folder set as "templates" in the config). The name can leave off the file's extension, so the above reads "weekly_email.txt". A template is plain text with values in double braces:
+//!
+//!       Hours for {{start|date:%d %b}} to {{end|date:%d %b %Y}}
+//!       {{#each jobs}}
+//!       {{@index}}. {{name|left:12}} {{time|hours}}h{{#if amount}} £{{amount|money}}{{/if}}
+//!       {{/each}}
+//!       Total: {{total}}
+//!
+//!   The values are ```start``` and ```end``` (the date range), ```total```, ```count``` (of sessions), ```amount``` (when every job has a rate), and the lists ```sessions```, ```jobs```, ```days``` and ```groups```. Each session has date, job, start, end, time, tags, note and amount. Each job, day and group has time, count, amount and its own sessions, with name (and rate for jobs) or date. ```{{#each list}}...{{/each}}``` repeats for every item, with ```{{@index}}``` counting from 1, and ```{{#if value}}...{{else}}...{{/if}}``` skips empty values. A block tag alone on its line leaves no blank line behind. Rates come from ```--rates``` or the config, as for html reports.
+//!
+//!   Filters follow a "|": ```hours``` and ```mins``` turn a time into a number, ```date:%A %d/%m``` formats a date, ```money``` gives 2 decimal places, ```join:sep``` joins a list, ```left:n``` and ```right:n``` pad to a width, ```upper```, ```lower``` and ```default:text``` for empty values. Only the rendered template goes to stdout, with messages such as "You have been clocked in..." on stderr, so the output can be piped straight into another program.
+//!
+//!   Serving a Dashboard
+//!   ------------------
+//!
+//!       work_tock serve --port 8377
+//!
+//!   serves a small dashboard at http://localhost:8377 for clocking in and out from a browser tab, with buttons for recent jobs and today's sessions. It only listens on localhost, and the file is read afresh for every request, so the command line can still be used alongside it. Clocking in and out append to the file just as ```-i``` and ```-o``` do, with the same checks, so ```undo``` takes them back out. The port can also be set with "serve_port" in the config.
+//!
+//!   The dashboard uses a json api, which scripts can use too:
+//!
+//!   * ```GET /api/status``` whether clocked in and to what, the time done today and this week, and recent jobs
+//!   * ```POST /api/in``` with "job", and optionally "tags" (comma separated, replacing the current tags) and "at" (a time), replying with the status and any budget messages that ```-i``` would print in "messages"
+//!   * ```POST /api/out``` with optionally "at", and "long_day" to clock out on a later day
+//!   * ```GET /api/sessions``` and ```GET /api/totals``` the sessions, or the total and time per job, filtered by "since", "until", "job", "under", "tag" and "group"
+//!
+//!   Parameters can be in the query string or a form encoded body. POST requests need an "X-Work-Tock" header, which stops other web pages open in the browser from clocking you in. A connection that takes more than 5 seconds to send its request is dropped.
+//!
+//!   Terminal UI
+//!   ------------------
+//!
+//!       work_tock tui
+//!
+//!   shows a full screen view of the file, redrawn every second: the open session with a live timer, today's and this week's totals, the last few jobs numbered 1 to 9, and a log of the latest sessions. Press a number to switch to that job, "i" to type the name of a new job, "o" to clock out, the up and down arrows to scroll the log, and "q", Esc or Ctrl-C to quit. Switching and clocking out append to the file just as ```-i``` and ```-o``` do, and the file is read again before each redraw, so changes made elsewhere show up. If the file cannot be read, the error is shown at the bottom and the last good view stays until it is fixed. It needs a terminal that understands ansi escapes, and "stty".
+//!
+//!   Watching the File
+//!   ------------------
+//!
+//!       work_tock -w -p --watch
+//!
+//!   keeps running and reprints the report every second, with the time on the open session ticking to the second. The file is read again whenever it is modified, so edits made by hand or by another tool show up straight away. If the file cannot be read (say half way through an edit) the error is shown until it changes again. All the filters work as usual, but ```--watch``` only shows the default report, so it cannot be used with subcommands or with clocking in and out. Stop it with Ctrl-C.
+//!
 //!   For more information use ```work_tock --help```
 //!
 
-
 extern crate work_tock_lib;
 
 use work_tock_lib::{
-    clockin,  Clockin, ClockAction,    STime, TokErr, gob
+    clockin, ClockAction, InData,    STime, TokErr, gob, insert, punch, amend, journal, report, timesheet, breakdown, tree, group, budget, balance, leave, rate, chart, calendar, html, markdown, serve, template, ics, import, csv_import, timeclock, term, tui
 };
+use work_tock_lib::report::Format;
 
 use gobble::Parser;
 
@@ -141,6 +412,7 @@ use chrono::{Datelike, Weekday};
 
 use clap_conf::*;
 
+#[allow(clippy::needless_borrows_for_generic_args)]
 fn append_to(fname: &str) -> Result<std::fs::File, failure::Error> {
     std::fs::OpenOptions::new()
         .append(true)
@@ -148,6 +420,429 @@ fn append_to(fname: &str) -> Result<std::fs::File, failure::Error> {
         .map_err(|e| e.into())
 }
 
+/// Append a line to the file, and journal everything this run has appended so "undo" can take it out again
+fn append_line(fname: &str, line: &str, run_start: &mut Option<usize>) -> Result<(), failure::Error> {
+    let start = std::fs::metadata(fname)?.len() as usize;
+    let mut f = append_to(fname)?;
+    writeln!(f, "{}", line)?;
+
+    let run_start = *run_start.get_or_insert(start);
+    let s = std::fs::read_to_string(fname)?;
+    let jpath = journal::journal_path(fname);
+    let mut entries = journal::read_entries(&std::fs::read_to_string(&jpath).unwrap_or_default())?;
+    journal::record(&mut entries, journal::Entry::since(&s, run_start));
+    std::fs::write(&jpath, journal::write_entries(&entries))?;
+    Ok(())
+}
+
+/// Replace the whole file, keeping the previous version as "<fname>.bak"
+fn write_with_backup(fname: &str, contents: &str) -> Result<(), failure::Error> {
+    std::fs::copy(fname, format!("{}.bak", fname))?;
+    std::fs::write(fname, contents)?;
+    Ok(())
+}
+
+/// Open the file in the users editor, at the given (1 based) line
+fn run_editor(editor: &str, fname: &str, line: Option<usize>) -> Result<(), failure::Error> {
+    let mut parts = editor.split_whitespace();
+    let mut cmd = std::process::Command::new(parts.next().unwrap_or("vi"));
+    cmd.args(parts);
+    if let Some(l) = line {
+        cmd.arg(format!("+{}", l));
+    }
+    if !cmd.arg(fname).status()?.success() {
+        return Err(TokErr::from("Editor did not exit cleanly").into());
+    }
+    Ok(())
+}
+
+/// The lines around a (0 based) line, with that line marked
+fn show_line(s: &str, line: usize) -> String {
+    let mut res = String::new();
+    for (i, l) in s.lines().enumerate().skip(line.saturating_sub(2)).take(5) {
+        let mark = if i == line { ">>" } else { "  " };
+        res.push_str(&format!("{} {:>5} | {}\n", mark, i + 1, l));
+    }
+    res
+}
+
+/// Messages about what was done go to stderr, when the output is meant for another program
+fn info(machine: bool, msg: &str) {
+    match machine {
+        false => println!("{}", msg),
+        true => eprintln!("{}", msg),
+    }
+}
+
+/// Answer one request to the server, reading the file afresh each time
+fn serve_request<'a, G: Getter<'a, String>>(cfg: &'a G, fname: &str, req: &serve::Request) -> Result<serve::Response, failure::Error> {
+    if !req.is_local() {
+        return Ok(serve::Response::error(403, "Only for localhost"));
+    }
+    if req.method == "POST" && !req.headers.contains_key(serve::WRITE_HEADER) {
+        return Ok(serve::Response::error(403, "Writes need the X-Work-Tock header"));
+    }
+    let today = Local::now().date().naive_local();
+    let now = match req.params.get("at") {
+        Some(t) => STime::from_str(t)?,
+        None => STime::now(),
+    };
+    //the sessions in the file as it is now, with the open one running to now
+    let read = || -> Result<(Vec<clockin::Session>, Option<InData>), failure::Error> {
+        let clocks = clockin::read_string(&std::fs::read_to_string(fname)?)?.clocks;
+        let (mut c_io, curr) = clockin::pair_sessions(clocks)?;
+        if let Some(c_data) = &curr {
+            let since = now.since(&today, c_data.time, &c_data.date);
+            c_io.push((c_data.clone(), since + c_data.time));
+        }
+        Ok((c_io, curr))
+    };
+    let data = clockin::read_string(&std::fs::read_to_string(fname)?)?;
+    let (c_io, curr) = read()?;
+    let mut messages = Vec::new();
+    match (req.method.as_ref(), req.path.as_ref()) {
+        ("GET", "/") => return Ok(serve::Response::html(serve::DASHBOARD)),
+        ("POST", "/api/in") => {
+            let job = req.params.get("job").filter(|j| !j.is_empty()).ok_or(TokErr::from("No job given"))?;
+            let set_tags: Option<Vec<String>> = req.params.get("tags")
+                .map(|t| t.split(',').map(str::trim).filter(|t| !t.is_empty()).map(String::from).collect());
+            let closed = match curr {
+                Some(_) => &c_io[..c_io.len() - 1],
+                None => &c_io[..],
+            };
+            let n_data = append_clockin(cfg, fname, closed, curr.as_ref(), job, set_tags.as_deref(), today, now)?;
+            messages = budget::clockin_notes(&data.budgets, &data.groups, &c_io, today, &n_data);
+        }
+        ("POST", "/api/out") => {
+            let c_data = curr.as_ref().ok_or(TokErr::from("Cannot clock out if not clocked in"))?;
+            let otime = punch::clock_out(c_data, today, now, req.params.contains_key("long_day"))?;
+            append_line(fname, &punch::out_line(otime), &mut None)?;
+        }
+        _ => {}
+    }
+    let (c_io, curr) = match req.method == "POST" {
+        true => read()?,
+        false => (c_io, curr),
+    };
+    match (req.method.as_ref(), req.path.as_ref()) {
+        ("GET", "/api/status") | ("POST", "/api/in") | ("POST", "/api/out") => Ok(serve::Response::json(serve::status_json(&c_io, curr.as_ref(), today, &messages))),
+        ("GET", "/api/sessions") => Ok(serve::Response::json(serve::sessions_json(&serve::filter(c_io, &req.params, &data.groups)?))),
+        ("GET", "/api/totals") => Ok(serve::Response::json(serve::totals_json(&serve::filter(c_io, &req.params, &data.groups)?))),
+        _ => Ok(serve::Response::error(404, "Not found")),
+    }
+}
+
+/// Time per job and the total, with each session listed first for "-p"
+fn default_report(c_io: Vec<(InData, STime)>, leave: &[leave::Leave], print: bool) -> String {
+    let mut res = String::new();
+    let mut r_times: BTreeMap<String, STime> = BTreeMap::new();
+    let mut t_time = STime::new(0, 0);
+    let mut last_dat = NaiveDate::from_ymd(1, 1, 1);
+    for (idat, otime) in c_io {
+        #[allow(clippy::map_clone)]
+        let tt = r_times
+            .get(&idat.job)
+            .map(|x| *x)
+            .unwrap_or(STime::new(0, 0));
+        t_time += otime - idat.time;
+        if print {
+            //maybe move out later
+            if last_dat != idat.date {
+                res.push_str(&format!("{}\n", idat.date.format("%d/%m/%Y")));
+                last_dat = idat.date;
+            }
+            res.push_str(&format!(
+                "  {}: {}-{} = {}   => {}\n",
+                idat.job,
+                idat.time,
+                otime,
+                otime - idat.time,
+                t_time
+            ));
+        }
+        r_times.insert(idat.job, tt + otime - idat.time);
+    }
+
+    res.push_str(&format!("\n{:?}\n\n", r_times));
+    res.push_str(&format!("Total Time = {}\n", t_time));
+    for (k, (n, t)) in leave::summary(leave) {
+        res.push_str(&format!("Leave {} = {} days, {} set hours\n", k, n, t));
+    }
+    res
+}
+
+/// The sessions and leave left by the filters, with a title naming any groups, and what was filtered
+struct Filtered {
+    sessions: Vec<(InData, STime)>,
+    leave: Vec<leave::Leave>,
+    title: String,
+    messages: Vec<String>,
+}
+
+fn filter_sessions<'a, G: Getter<'a, String>>(cfg: &'a G, clap: &ArgMatches, groups: &group::Groups, mut c_io: Vec<(InData, STime)>, mut leave: Vec<leave::Leave>) -> Result<Filtered, failure::Error> {
+    let mut msgs = Vec::new();
+
+    if cfg.bool_flag("this_week", Filter::Arg) {
+        let dt = Local::today().naive_local();
+        let wk = dt.iso_week().week();
+        let (st, fin) = report::week_of(dt);
+        msgs.push(format!("Filtering by week {}", wk));
+        c_io.retain(|(ind, _)| ind.date >= st && ind.date <= fin);
+        leave.retain(|l| l.date >= st && l.date <= fin);
+    }
+
+    let mut title = "Work report".to_string();
+    if let Some(grps) = clap.values_of("group"){
+        let grps: Vec<&str> = grps.collect();
+        title = format!("{}: {}", title, grps.join(", "));
+        msgs.push(format!("Filtering by group {}",grps.join(", ")));
+        for g in &grps {
+            groups.get(*g).ok_or(TokErr::Mess(format!("Group not defined \"{}\"",g) ))?;
+        }
+        c_io.retain(|(ind,_)| grps.iter().any(|g|group::in_group(groups,g,ind)));
+    }
+
+    if let Some(wks) = cfg.grab().arg("week").done() {
+        let dt = Local::today();
+        let wk = wks.parse::<u32>()?;
+        //.map_err(|_| "Could not parse week value")?;
+        let st = NaiveDate::from_isoywd(dt.year(), wk, Weekday::Mon);
+        let fin = NaiveDate::from_isoywd(dt.year(), wk, Weekday::Sun);
+        msgs.push(format!("Filtering by week {}", wk));
+        c_io.retain(|(ind, _)| ind.date >= st && ind.date <= fin);
+        leave.retain(|l| l.date >= st && l.date <= fin);
+    }
+
+    //local closure for month filter
+    let month_s_fin = |yr, m| {
+        (
+            NaiveDate::from_ymd(yr, m, 1),
+            match m {
+                12 => NaiveDate::from_ymd(yr + 1, 1, 1),
+                _ => NaiveDate::from_ymd(yr, m + 1, 1),
+            },
+        )
+    };
+
+    if cfg.bool_flag("this_month", Filter::Arg) {
+        let dt = Local::today();
+        let (st, fin) = month_s_fin(dt.year(), dt.month());
+        c_io.retain(|(ind, _)| ind.date >= st && ind.date < fin);
+        leave.retain(|l| l.date >= st && l.date < fin);
+    }
+
+    if let Some(mth) = cfg.grab().arg("month").done() {
+        let dt = Local::today();
+        let (st, fin) = month_s_fin(dt.year(), mth.parse()?);
+        c_io.retain(|(ind, _)| ind.date >= st && ind.date < fin);
+        leave.retain(|l| l.date >= st && l.date < fin);
+    }
+
+    //TODO filter by given date
+    if cfg.bool_flag("today", Filter::Arg) {
+        let dt = Local::today().naive_local();
+        msgs.push("Filtering by Today".to_string());
+        c_io.retain(|(ind, _)| ind.date == dt);
+        leave.retain(|l| l.date == dt);
+    }
+
+    if let Some(d) = cfg.grab().arg("since").done() {
+        let dt = clockin::read_date(&d)?;
+        c_io.retain(|(ind, _)| ind.date >= dt);
+        leave.retain(|l| l.date >= dt);
+    }
+
+    if let Some(d) = cfg.grab().arg("until").done() {
+        let dt = clockin::read_date(&d)?;
+        c_io.retain(|(ind, _)| ind.date <= dt);
+        leave.retain(|l| l.date <= dt);
+    }
+
+    if let Some(jb) = cfg.grab().arg("job").done() {
+        c_io.retain(|(ind, _)| ind.job == jb);
+    }
+
+    if let Some(jbs) = cfg.grab().arg("jobstart").done() {
+        c_io.retain(|(ind, _)| ind.job.starts_with(&jbs));
+    }
+
+    if let Some(root) = cfg.grab().arg("subtree").done() {
+        c_io.retain(|(ind, _)| tree::in_subtree(&ind.job, &root));
+    }
+
+    if let Some(tgs) = clap.values_of("tag") {
+        let tgs: Vec<String> = tgs.map(String::from).collect();
+        let any = cfg.bool_flag("any_tag", Filter::Arg);
+        c_io.retain(|(ind, _)| report::tags_match(&ind.tags, &tgs, any));
+    }
+
+    if cfg.bool_flag("untagged", Filter::Arg) {
+        c_io.retain(|(ind, _)| ind.tags.is_empty());
+    }
+
+    Ok(Filtered{sessions: c_io, leave, title, messages: msgs})
+}
+
+/// The sessions from the file, paired when it was last read
+struct Watched {
+    sessions: Vec<(InData, STime)>,
+    open: Option<InData>,
+    groups: group::Groups,
+    leave: Vec<leave::Leave>,
+}
+
+fn read_watched(fname: &str) -> Result<Watched, failure::Error> {
+    let data = clockin::read_string(&std::fs::read_to_string(fname)?)?;
+    let (sessions, open) = clockin::pair_sessions(data.clocks)?;
+    Ok(Watched{sessions, open, groups: data.groups, leave: data.leave})
+}
+
+/// The open session ticking to the second, then the filtered report
+fn watch_screen<'a, G: Getter<'a, String>>(cfg: &'a G, clap: &ArgMatches, w: &Watched) -> Result<String, failure::Error> {
+    let now_dt = Local::now().naive_local();
+    let (today, now) = (now_dt.date(), STime::now());
+    let mut c_io = w.sessions.clone();
+    let mut res = match &w.open {
+        Some(c_data) => {
+            c_io.push((c_data.clone(), now.since(&today, c_data.time, &c_data.date) + c_data.time));
+            format!("Clocked in to {} since {}, {}\n", c_data.job, c_data.time, tui::elapsed(c_data, now_dt))
+        }
+        None => "Not clocked in\n".to_string(),
+    };
+    let f = filter_sessions(cfg, clap, &w.groups, c_io, w.leave.clone())?;
+    for m in &f.messages {
+        res.push_str(&format!("{}\n", m));
+    }
+    res.push_str(&default_report(f.sessions, &f.leave, cfg.bool_flag("print", Filter::Arg)));
+    Ok(res)
+}
+
+/// Redraw every second, reading the file again whenever it has been modified
+fn run_watch<'a, G: Getter<'a, String>>(cfg: &'a G, clap: &ArgMatches, fname: &str) -> Result<(), failure::Error> {
+    let mut modified = None;
+    let mut watched = read_watched(fname);
+    print!("\x1b[2J");
+    loop {
+        let m = std::fs::metadata(fname).and_then(|m| m.modified()).ok();
+        if m != modified {
+            modified = m;
+            watched = read_watched(fname);
+        }
+        let body = match &watched {
+            Ok(w) => watch_screen(cfg, clap, w).unwrap_or_else(|e| format!("{}\n", e)),
+            Err(e) => format!("Could not read the file, waiting for it to change:\n{}\n", e),
+        };
+        let screen = format!("Watching {} (Ctrl-C to stop)\n\n{}", fname, body);
+        print!("\x1b[H{}\x1b[J", screen.replace('\n', "\x1b[K\n"));
+        std::io::stdout().flush()?;
+        std::thread::sleep(std::time::Duration::from_secs(1));
+    }
+}
+
+/// Clock in as "-i" does, for the server and the tui
+#[allow(clippy::too_many_arguments)]
+fn append_clockin<'a, G: Getter<'a, String>>(cfg: &'a G, fname: &str, c_io: &[(InData, STime)], curr: Option<&InData>, job: &str, set_tags: Option<&[String]>, today: NaiveDate, now: STime) -> Result<InData, failure::Error> {
+    check_case(cfg, job)?;
+    if let Some(c_data) = curr {
+        punch::switch_out(c_data, today, now)?;
+    }
+    let last = curr.or_else(|| c_io.last().map(|x| &x.0));
+    append_line(fname, &punch::in_line(last, job, None, set_tags, today, now), &mut None)?;
+    Ok(punch::new_session(last, job, set_tags, today, now))
+}
+
+/// A full screen view that redraws every second, and clocks in and out on key presses
+fn run_tui<'a, G: Getter<'a, String>>(cfg: &'a G, fname: &str) -> Result<(), failure::Error> {
+    let _raw = tui::Raw::enter()?;
+    let keys = tui::keys();
+    let (mut scroll, mut message, mut input) = (0, String::new(), None::<String>);
+    //the last good read of the file, kept while it cannot be read (say half way through an edit)
+    let mut last = (Vec::new(), None);
+    loop {
+        let read = std::fs::read_to_string(fname).map_err(failure::Error::from)
+            .and_then(|s| Ok(clockin::pair_sessions(clockin::read_string(&s)?.clocks)?));
+        let read_err = match read {
+            Ok(r) => { last = r; None }
+            Err(e) => Some(format!("Could not read {}: {}", fname, e)),
+        };
+        let (closed, curr): (Vec<(InData, STime)>, Option<InData>) = last.clone();
+        let mut c_io = closed.clone();
+        let now_dt = Local::now().naive_local();
+        let (today, now) = (now_dt.date(), STime::now());
+        if let Some(c_data) = &curr {
+            c_io.push((c_data.clone(), now.since(&today, c_data.time, &c_data.date) + c_data.time));
+        }
+        print!("{}", tui::draw(&tui::View {
+            file: fname,
+            sessions: &c_io,
+            open: curr.as_ref(),
+            now: now_dt,
+            scroll,
+            message: read_err.as_deref().unwrap_or(&message),
+            input: input.as_deref(),
+            size: term::term_size(),
+        }));
+        std::io::stdout().flush()?;
+
+        let key = match keys.recv_timeout(std::time::Duration::from_secs(1)) {
+            Ok(k) => k,
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(_) => return Ok(()),
+        };
+        if key == tui::Key::CtrlC {
+            return Ok(());
+        }
+        let mut clock_in = None;
+        if let Some(i) = input.as_mut() {
+            match key {
+                tui::Key::Char(c) => i.push(c),
+                tui::Key::Backspace => { i.pop(); }
+                tui::Key::Esc => input = None,
+                tui::Key::Enter => clock_in = input.take().filter(|i| !i.trim().is_empty()).map(|i| i.trim().to_string()),
+                _ => {}
+            }
+        } else {
+            match key {
+                tui::Key::Char('q') | tui::Key::Esc => return Ok(()),
+                tui::Key::Char('i') => input = Some(String::new()),
+                tui::Key::Char('o') if read_err.is_some() => message = "Fix the file before clocking out".to_string(),
+                tui::Key::Char('o') => {
+                    let res = curr.as_ref().ok_or(TokErr::from("Cannot clock out if not clocked in"))
+                        .and_then(|c_data| punch::clock_out(c_data, today, now, false))
+                        .map_err(failure::Error::from)
+                        .and_then(|otime| append_line(fname, &punch::out_line(otime), &mut None).map(|_| otime));
+                    message = match res {
+                        Ok(otime) => format!("Clocked out at {}", otime),
+                        Err(e) => e.to_string(),
+                    };
+                }
+                tui::Key::Char(c @ '1'..='9') => {
+                    clock_in = tui::recent_jobs(&c_io).get(c as usize - '1' as usize).cloned();
+                }
+                tui::Key::Up => scroll = (scroll + 1).min(c_io.len().saturating_sub(1)),
+                tui::Key::Down => scroll = scroll.saturating_sub(1),
+                _ => {}
+            }
+        }
+        if clock_in.is_some() && read_err.is_some() {
+            message = "Fix the file before clocking in".to_string();
+        } else if let Some(job) = clock_in {
+            message = match append_clockin(cfg, fname, &closed, curr.as_ref(), &job, None, today, now) {
+                Ok(_) => format!("Clocked in to {} at {}", job, now),
+                Err(e) => e.to_string(),
+            };
+        }
+    }
+}
+
+fn confirm(question: &str) -> Result<bool, failure::Error> {
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut res = String::new();
+    std::io::stdin().read_line(&mut res)?;
+    Ok(res.trim().to_lowercase().starts_with('y'))
+}
+
 
 fn main() -> Result<(), failure::Error> {
     let clap = clap_app!(
@@ -179,15 +874,113 @@ fn main() -> Result<(), failure::Error> {
             (@arg since: --since +takes_value "Filter Since given date (inclusive)")
             (@arg until: --until +takes_value "Filter until given date (inclusive)")
             (@arg job: --job +takes_value "Filter by Job")
-            (@arg group: -g --group + takes_value "Filter by group")
+            (@arg group: -g --group +takes_value +multiple number_of_values(1) "Filter by group, give more than once for any of several")
             (@arg jobstart: --job_s +takes_value "Filter by Job Starts with")
-            (@arg tag: --tag +takes_value "Filter by Tag")
+            (@arg subtree: --under +takes_value "Filter by a job and every job under it, eg: --under client/backend")
+            (@arg tag: --tag +takes_value +multiple number_of_values(1) "Filter by Tag, give more than once to need all of them")
+            (@arg any_tag: --any_tag "With several --tag filters, keep sessions with any of them")
+            (@arg untagged: --untagged "Filter to sessions with no tags")
+            (@arg format: --format +takes_value "Output format for reports: text, csv, md or html")
+            (@arg template: --template +takes_value "Report using a template from ~/.config/work_tock/templates")
+            (@arg rates: --rates +takes_value "Hourly rates for html reports, eg: 50 or client_a=80,client_b=65")
+            (@arg by: --by +takes_value "Subtotals by day, week, month, year, job, tag or group, eg: --by month,job")
+            (@arg watch: --watch "Keep showing the report, reading the file again when it changes")
             (@arg camel: --camel "Force Camel Case on job input")
             (@arg snake: --snake "Force Camel Case on job input")
             (@subcommand complete  => )
+            (@subcommand add =>
+                (about: "Add a finished session in date order, eg: work_tock add car_wash 12:30 13:50 -d 23/01/2019")
+                (@arg job: +required "Job name")
+                (@arg start: +required "Start time hh:mm")
+                (@arg end: +required "End time hh:mm")
+                (@arg date: -d --date +takes_value "Date of the session, (default today)")
+                (@arg tags: -t --tag +takes_value +multiple "Tags for the session")
+                (@arg yes: --yes "Write without asking for confirmation")
+            )
+            (@subcommand amend =>
+                (about: "Change the most recent clockin, eg: work_tock amend --job car_wash --at 12:30")
+                (@arg job: --job +takes_value "Change the job")
+                (@arg at: --at +takes_value "Change the start time")
+                (@arg tags: -t --tag +takes_value +multiple "Replace the tags")
+                (@arg clear_tags: --clear_tags "Remove all tags")
+                (@arg note: -n --note +takes_value "Set the note, (a comment on the clockin line)")
+                (@arg end: --end +takes_value "Change the end time of the most recent session")
+            )
+            (@subcommand import =>
+                (about: "Add sessions read from another format in date order, eg: work_tock import ics meetings.ics")
+                (@arg from: +required "File format: ics, csv or timeclock")
+                (@arg input: +required "File to read")
+                (@arg rules: --rules +takes_value "Rules file matching event titles to jobs (default config import_rules)")
+                (@arg columns: --columns +takes_value "csv columns, eg: date=Day,start=From,end=To,job=Project,note=4")
+                (@arg no_header: --no_header "The csv file has no header row")
+                (@arg date_format: --date_format +takes_value "csv date format, eg: %m/%d/%Y")
+                (@arg dry_run: -n --dry_run "Show what would be added without writing")
+            )
+            (@subcommand undo =>
+                (about: "Remove the lines appended by the last clock in or out")
+            )
+            (@subcommand push =>
+                (about: "Interrupt the current job with another, \"pop\" goes back to it")
+                (@arg job: +required "Job to interrupt with")
+                (@arg tags: -t --tag +takes_value +multiple "Tags for the new job")
+            )
+            (@subcommand pop =>
+                (about: "Go back to the job and tags interrupted by the last \"push\"")
+            )
+            (@subcommand timesheet =>
+                (about: "Grid of jobs against weekdays, for each week in the filtered range")
+            )
+            (@subcommand tree =>
+                (about: "Totals for job names split on '/', rolled up to their parents")
+            )
+            (@subcommand budget =>
+                (about: "Time used against each budget in its current week or month")
+            )
+            (@subcommand balance =>
+                (about: "Target against actual hours, with the running flextime balance")
+                (@arg weekly: -w --weekly "One row per week instead of per day")
+            )
+            (@subcommand export =>
+                (about: "Write the filtered sessions in another format")
+                (@arg to: +required "File format: ics or timeclock")
+                (@arg output: -o --output +takes_value "Write to a file instead of stdout")
+                (@arg open: --open "Include the open session, marked as in progress")
+            )
+            (@subcommand calendar =>
+                (about: "Grid of each month showing how much was worked each day")
+                (@arg year: -y --year "One grid per year, a column per week")
+                (@arg hours: --hours "Show whole hours instead of shading")
+            )
+            (@subcommand chart =>
+                (about: "Bar charts of time per job, tag or group, and per day")
+                (@arg by: --by +takes_value "job, tag or group (default job)")
+                (@arg width: --width +takes_value "Chart width (default the terminal width)")
+            )
+            (@subcommand leave =>
+                (about: "Holiday, sick and other leave in the filtered range")
+            )
+            (@subcommand groups =>
+                (about: "Time per group, and per job within each group, for the filtered range")
+            )
+            (@subcommand tags =>
+                (about: "Time per tag, and per tag within each job, for the filtered range")
+            )
+            (@subcommand serve =>
+                (about: "Serve a dashboard and json api on localhost for clocking in and out from a browser")
+                (@arg port: -p --port +takes_value "Port to listen on (default config serve_port or 8377)")
+            )
+            (@subcommand tui =>
+                (about: "Full screen view with a live timer, for switching jobs with one key")
+            )
+            (@subcommand edit =>
+                (about: "Open the file in $EDITOR and check it after")
+                (@arg today: -t --today "Start at today's entries")
+                (@arg line: -l --line +takes_value "Start at the given line")
+            )
     )
     .get_matches();
 
+    #[allow(clippy::needless_borrows_for_generic_args)]
     let cfg = clap_conf::with_toml_env(&clap, &["{HOME}/.config/work_tock/init.toml"]);
     //core options
     let fname = cfg
@@ -198,7 +991,15 @@ fn main() -> Result<(), failure::Error> {
         .expect("No File given");
 
 
+    if cfg.bool_flag("watch", Filter::Arg) {
+        if clap.subcommand_name().is_some() || ["clockin", "clockout", "quickin"].iter().any(|f| clap.is_present(f)) {
+            return Err(TokErr::from("--watch only shows the default report, it cannot be used with subcommands or clocking in and out").into());
+        }
+        return run_watch(&cfg, &clap, &fname);
+    }
+
     //Tab Complete list
+    #[allow(clippy::println_empty_string, clippy::single_match)]
     if let Some(_sc) = clap.subcommand_matches("complete"){
         let s = std::fs::read_to_string(&fname)?; //.map_err(|_| format!("Could not read file: {}", fname))?;
         let mut mp = std::collections::BTreeSet::new();
@@ -219,11 +1020,210 @@ fn main() -> Result<(), failure::Error> {
         return Ok(()) ;
     }
 
+    if let Some(sc) = clap.subcommand_matches("add"){
+        let job = sc.value_of("job").unwrap_or("").to_string();
+        check_case(&cfg, &job)?;
+        let date = match sc.value_of("date"){
+            Some(d)=>clockin::read_date(d)?,
+            None=>Local::now().date().naive_local(),
+        };
+        let idat = InData{
+            time : STime::from_str(sc.value_of("start").unwrap_or(""))?,
+            date,
+            job,
+            tags : sc.values_of("tags").map(|v|v.map(String::from).collect()).unwrap_or_default(),
+            line : 0,
+            note : None,
+        };
+        let out = STime::from_str(sc.value_of("end").unwrap_or(""))?;
+
+        let s = std::fs::read_to_string(&fname)?;
+        let ins = insert::plan_insert(&s, &idat, out)?;
+        println!("{}",ins.preview(&s,3));
+        if sc.is_present("yes") || confirm("Write these lines?")? {
+            write_with_backup(&fname, &ins.apply(&s))?;
+            println!("Added {} {}-{}",idat,idat.time,out);
+        }
+        return Ok(());
+    }
+
+    if clap.subcommand_matches("tui").is_some(){
+        return run_tui(&cfg, &fname);
+    }
+
+    if let Some(sc) = clap.subcommand_matches("serve"){
+        let port = match sc.value_of("port") {
+            Some(p) => p.to_string(),
+            None => cfg.grab().conf("config.serve_port").def("8377"),
+        };
+        let listener = std::net::TcpListener::bind(("127.0.0.1", port.parse::<u16>()?))?;
+        println!("Serving {} on http://localhost:{}", fname, port);
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("Connection failed: {}", e);
+                    continue;
+                }
+            };
+            //one slow client must not hold up the rest
+            let timeout = Some(std::time::Duration::from_secs(serve::TIMEOUT_SECS));
+            if let Err(e) = stream.set_read_timeout(timeout).and_then(|_| stream.set_write_timeout(timeout)) {
+                eprintln!("Connection failed: {}", e);
+                continue;
+            }
+            let res = match serve::Request::read(&mut std::io::BufReader::new(&stream)) {
+                Ok(req) => serve_request(&cfg, &fname, &req).unwrap_or_else(|e| serve::Response::error(400, &e.to_string())),
+                Err(e) => serve::Response::error(400, &e.to_string()),
+            };
+            if let Err(e) = res.write_to(&mut stream) {
+                eprintln!("Could not reply: {}", e);
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(sc) = clap.subcommand_matches("import"){
+        let input = std::fs::read_to_string(sc.value_of("input").unwrap_or(""))?;
+        let read_rules = |rpath: String| -> Result<Vec<import::Rule>, TokErr> {
+            let rpath = clap_conf::replace::replace_env(&rpath).map_err(|e| TokErr::Mess(format!("{:?}", e)))?;
+            let rules = std::fs::read_to_string(&rpath)
+                .map_err(|_| TokErr::Mess(format!("Could not read import rules \"{}\"", rpath)))?;
+            import::read_rules(&rules)
+        };
+        let (sessions, skipped) = match sc.value_of("from").unwrap_or("") {
+            "ics" => {
+                let rules = read_rules(match sc.value_of("rules") {
+                    Some(r) => r.to_string(),
+                    None => cfg.grab().conf("config.import_rules").def("{HOME}/.config/work_tock/import_rules"),
+                })?;
+                let (events, mut skipped) = ics::read_events(&input)?;
+                let (sessions, unmatched) = ics::to_sessions(&events, &rules);
+                skipped.extend(unmatched.into_iter().map(|u| format!("{} (no rule matched)", u)));
+                (sessions, skipped)
+            }
+            "csv" => {
+                let spec = csv_import::Spec{
+                    columns: csv_import::Columns::parse(sc.value_of("columns").unwrap_or(""))?,
+                    has_head: !sc.is_present("no_header"),
+                    date_format: sc.value_of("date_format").map(String::from),
+                    rules: match sc.value_of("rules") {
+                        Some(r) => read_rules(r.to_string())?,
+                        None => Vec::new(),
+                    },
+                };
+                csv_import::to_sessions(&input, &spec)
+            }
+            "timeclock" => timeclock::import(&input),
+            f => return Err(TokErr::Mess(format!("Cannot import from \"{}\", use ics, csv or timeclock", f)).into()),
+        };
+        for sk in &skipped {
+            println!("Skipped: {}", sk);
+        }
+        let s = std::fs::read_to_string(&fname)?;
+        let (sessions, dups) = import::new_only(&s, sessions)?;
+        if dups > 0 {
+            println!("Skipped {} sessions already in the file", dups);
+        }
+        if sessions.is_empty() {
+            println!("Nothing to import");
+            return Ok(());
+        }
+        let (res, preview, refused) = import::insert_all(&s, &sessions);
+        print!("{}", preview);
+        for r in &refused {
+            println!("Skipped: {}", r);
+        }
+        let added = sessions.len() - refused.len();
+        if added == 0 {
+            println!("Nothing to import");
+            return Ok(());
+        }
+        if sc.is_present("dry_run") {
+            println!("Dry run: {} sessions would be added", added);
+            return Ok(());
+        }
+        write_with_backup(&fname, &res)?;
+        println!("Added {} sessions", added);
+        return Ok(());
+    }
+
+    if let Some(sc) = clap.subcommand_matches("amend"){
+        if let Some(j) = sc.value_of("job"){
+            check_case(&cfg, j)?;
+        }
+        let tags = match sc.values_of("tags"){
+            Some(v)=>Some(v.map(String::from).collect()),
+            None if sc.is_present("clear_tags") => Some(Vec::new()),
+            None=>None,
+        };
+        let am = amend::Amend{
+            job : sc.value_of("job").map(String::from),
+            time : sc.value_of("at").map(STime::from_str).transpose()?,
+            tags,
+            note : sc.value_of("note").map(String::from),
+            end : sc.value_of("end").map(STime::from_str).transpose()?,
+        };
+
+        let s = std::fs::read_to_string(&fname)?;
+        let res = amend::apply_edits(&s, &amend::plan_amend(&s, &am)?);
+        print!("{}",amend::show_changes(&s,&res));
+        write_with_backup(&fname, &res)?;
+        return Ok(());
+    }
+
+    if let Some(sc) = clap.subcommand_matches("edit"){
+        let editor = cfg.grab().conf("config.editor").env("EDITOR").env("VISUAL").def("vi");
+        let mut line = match sc.value_of("line"){
+            Some(l)=>Some(l.parse::<usize>()?),
+            None if sc.is_present("today") => {
+                let s = std::fs::read_to_string(&fname)?;
+                let today = Local::today().naive_local();
+                let today_line = clockin::date_lines(&s)?.into_iter().rev().find(|(_,d)|*d == today).map(|(l,_)|l + 1);
+                Some(today_line.unwrap_or_else(||s.lines().count()))
+            }
+            None=>None,
+        };
+        loop {
+            run_editor(&editor, &fname, line)?;
+            let s = std::fs::read_to_string(&fname)?;
+            let e = match clockin::read_string(&s).and_then(|d|Ok(clockin::pair_sessions(d.clocks)?)){
+                Ok(_)=> {
+                    println!("File reads OK");
+                    return Ok(());
+                }
+                Err(e)=>e,
+            };
+            println!("{}",e);
+            if let Some(l) = e.line(){
+                print!("{}",show_line(&s,l));
+            }
+            if !confirm("Re-open the editor?")? {
+                return Err(e.into());
+            }
+            line = e.line().map(|l|l+1).or(line);
+        }
+    }
+
+    if clap.subcommand_matches("undo").is_some(){
+        let jpath = journal::journal_path(&fname);
+        let mut entries = journal::read_entries(&std::fs::read_to_string(&jpath).unwrap_or_default())?;
+        let last = entries.pop().ok_or(TokErr::from("Nothing to undo"))?;
+
+        let s = std::fs::read_to_string(&fname)?;
+        let res = journal::remove_entry(&s, &last)?;
+        print!("{}",amend::show_changes(&s,&res));
+        write_with_backup(&fname, &res)?;
+        std::fs::write(&jpath, journal::write_entries(&entries))?;
+        return Ok(());
+    }
+
 
 
 
 
     let s = std::fs::read_to_string(&fname)?; //.map_err(|_| format!("Could not read file: {}", fname))?;
+    #[allow(unused_mut)]
     let mut clock_data = match clockin::read_string(&s){
         Ok(c)=>c,
         Err(e)=> {
@@ -235,6 +1235,7 @@ fn main() -> Result<(), failure::Error> {
     if let Some(rlist) =clap.values_of("read"){
             for r in rlist{
                 let s2 = std::fs::read_to_string(&fname)?; //.map_err(|_| format!("Could not read file: {}", fname))?;
+                #[allow(unused_variables)]
                 let cdata2 = match clockin::read_string(&s2) {
                     Ok(c)=>c,
                     Err(e)=>{
@@ -247,32 +1248,9 @@ fn main() -> Result<(), failure::Error> {
             }
     }
 
-    let mut curr = None;
-    let mut c_io = Vec::new();
     //Get outs with ins so filter makes sense
     //If currently clocked in leaves curr as an option to be added later
-    for c in clock_data.clocks {
-        match c {
-            Clockin::In(data) => {
-                if let Some(cin) = curr {
-                    c_io.push((cin, data.time));
-                }
-                curr = Some(data);
-            }
-            Clockin::Out(cout) => {
-                match curr {
-                    Some(data) => {
-                        if cout < data.time {
-                            return Err(TokErr::NegativeTime.on_line(data.line).into());
-                        }
-                        c_io.push((data, cout));
-                    }
-                    None => println!("Two Out's in a row"),
-                }
-                curr = None;
-            }
-        }
-    }
+    let (mut c_io, mut curr) = clockin::pair_sessions(clock_data.clocks)?;
 
     let today = match cfg.grab().arg("ondate").done(){
         Some(s)=>clockin::read_date(&s)?,
@@ -290,69 +1268,70 @@ fn main() -> Result<(), failure::Error> {
     };
         
 
+    let format = match cfg.grab().arg("format").conf("config.format").done(){
+        Some(f)=>f.parse()?,
+        None=>Format::Text,
+    };
+    //stdout is kept for the output itself when another program will read it
+    let machine = format != Format::Text || clap.subcommand_matches("export").is_some() || clap.is_present("template");
+
+    //where this run started appending, for the journal
+    let mut run_start = None;
+
     if cfg.bool_flag("clockout", Filter::Arg) {
         let c_data = curr
             .take()
             .ok_or(TokErr::from("Cannot clock out if not clocked in"))?;
 
-        if today > c_data.date && !cfg.bool_flag("long_day", Filter::Arg) {
-            return Err(TokErr::from(format!(
-                "Last Clockin was not today: {}. Please use -l to confirm long day",
-                c_data
-            ))
-            .into());
-        }
+        let otime = punch::clock_out(&c_data, today, now, cfg.bool_flag("long_day", Filter::Arg))?;
 
-        let since = now.since(&today,c_data.time, &c_data.date);
-        if since < STime::new(0,0) {
-            return Err(TokErr::from(format!("Cannot clockout before clockin")).into());
-        }
-        let mut f = append_to(&fname)?;
-        let otime = since + c_data.time;
-
-        writeln!(f, "  -{}", otime)?; //.map_err(|e| format!("{:?}", e))?;
-        println!("You are now clocking out from {} at {}", c_data, otime);
+        append_line(&fname, &punch::out_line(otime), &mut run_start)?;
+        info(machine, &format!("You are now clocking out from {} at {}", c_data, otime));
         c_io.push((c_data, otime));
     }
 
     if let Some(_tm) = cfg.grab().arg("clockoutat").done() {
-        println!(r#""--outat <time>" has been replaced by "-o -a <time>""#)
+        info(machine, r#""--outat <time>" has been replaced by "-o -a <time>""#)
     }
 
     let mut clockin = None;
 
+    #[allow(clippy::get_last_with_len)]
     if cfg.bool_flag("quickin", Filter::Arg) {
         clockin = Some(c_io.get(c_io.len() - 1).map(|x|x.0.job.clone()).ok_or(TokErr::from("no previous job"))?);
     }
 
     if let Some(istr) = cfg.grab().arg("clockin").done() {
-        //camel case required
-        let f_camel = cfg.bool_flag("camel",Filter::Arg) || cfg.bool_flag("config.camel",Filter::Conf);
-        if f_camel && istr.contains("_"){
-            return Err(TokErr::from("You have required CamelCase job entires").into());
-        }
-        //snake case required{
-        let f_snake = cfg.bool_flag("snake",Filter::Arg) || cfg.bool_flag("config.snake",Filter::Conf);
-        if f_snake && istr.chars().find(|c|c.is_uppercase()).is_some(){
-            return Err(TokErr::from("You have required snake_case job entires").into());
-
-        }
+        check_case(&cfg, &istr)?;
         clockin = Some(istr);
 
     }
 
+    //push and pop mark the clockin, and set its tags exactly
+    let mut marker = None;
+    let mut set_tags = None;
+    if let Some(sc) = clap.subcommand_matches("push") {
+        let job = sc.value_of("job").unwrap_or("").to_string();
+        check_case(&cfg, &job)?;
+        let c_data = curr.as_ref().ok_or(TokErr::from("Not clocked in, there is nothing to interrupt"))?;
+        info(machine, &format!("{} is waiting, use \"work_tock pop\" to go back to it", c_data));
+        marker = Some(">>");
+        set_tags = Some(sc.values_of("tags").map(|v|v.map(String::from).collect()).unwrap_or_default());
+        clockin = Some(job);
+    }
+    if clap.subcommand_matches("pop").is_some() {
+        let back = clock_data.stack.last().ok_or(TokErr::from("There is no interrupted job to go back to"))?;
+        info(machine, &format!("Going back to {} with tags {:?}", back, back.tags));
+        marker = Some("<<");
+        set_tags = Some(back.tags.clone());
+        clockin = Some(back.job.clone());
+    }
+
     if let Some(job) = clockin{
         //first check that we are not clockedin on a different date
         if let Some(c_data) = curr.take() {
-            if c_data.date != today {
-                return Err(TokErr::from("You are currently clocked in from a different date, Please clockout from that before clocking in.").into());
-            }
-            let since = now.since(&today,c_data.time, &c_data.date);
-            if since < STime::new(0,0) {
-                return Err(TokErr::from("You are currently clocked in since after the given time. Cannot clockout before clocking in").into());
-
-            }
-            println!("You are now clocking out from {} at {} ({}hrs)", c_data, now,since);
+            let since = punch::switch_out(&c_data, today, now)?;
+            info(machine, &format!("You are now clocking out from {} at {} ({}hrs)", c_data, now,since));
             c_io.push((c_data, now));
         }
         
@@ -362,162 +1341,185 @@ fn main() -> Result<(), failure::Error> {
         } else {
             "today".to_string()
         };
-        println!(// message 
+        info(machine, &format!(// message 
             "You are now clocking in {} at {} for \"{}\"",
             date_str,
             now,
             job
-        );
+        ));
 
+        #[allow(clippy::get_last_with_len)]
         let lastjob = c_io.get(c_io.len() - 1).map(|x| x.clone().0);//Option
-        let f_line = match lastjob {
-            Some(lj) =>{
-                let mut f = if lj.date != today{
-                    today.format("%d/%m/%Y\n\t").to_string()
-                }else { "\t".to_string()};
-                if lj.job != job {
-                    f.push_str(&format!("{},",do_quotes(&job)));
-                }
-                f.push_str(&now.to_string());
-                f
-            }
-            None=>{
-                format!("{}\n\t{},{}",today.format("%d/%m/%Y"),job,now)
-            }
-        };
-         
+        let n_data = punch::new_session(lastjob.as_ref(), &job, set_tags.as_deref(), today, now);
+        for m in budget::clockin_notes(&clock_data.budgets, &clock_data.groups, &c_io, today, &n_data) {
+            info(machine, &m);
+        }
+        let f_line = punch::in_line(lastjob.as_ref(), &job, marker, set_tags.as_deref(), today, now);
 
-        let mut f = append_to(&fname)?;
-        writeln!(f, "{}", f_line)?
+        append_line(&fname, &f_line, &mut run_start)?;
     }
+    let open = curr.clone();
     if let Some(c_data) = curr {
         let since_last = now.since(&today,c_data.time, &c_data.date);
-        println!(
+        info(machine, &format!(
             "You have been clocked in for {} for {} hours",
             c_data, since_last,
-        );
+        ));
         let otime = since_last + c_data.time;
         c_io.push((c_data, otime));
     }
 
-    //filter.
+    if clap.subcommand_matches("budget").is_some() {
+        let usages = budget::usage(&clock_data.budgets, &clock_data.groups, &c_io, today);
+        print!("{}", budget::render(&usages, format));
+        return Ok(());
+    }
 
-    if cfg.bool_flag("this_week", Filter::Arg) {
-        let dt = Local::today();
-        let wk = dt.iso_week().week();
-        let st = NaiveDate::from_isoywd(dt.year(), dt.iso_week().week(), Weekday::Mon);
-        let fin = NaiveDate::from_isoywd(dt.year(), dt.iso_week().week(), Weekday::Sun);
-        println!("Filtering by week {}", wk);
-        c_io.retain(|(ind, _)| ind.date >= st && ind.date <= fin);
+    //filter, keeping all the sessions for numbering calendar events through each day
+    let all_sessions = c_io.clone();
+    let Filtered{sessions: mut c_io, leave, title, messages} = filter_sessions(&cfg, &clap, &clock_data.groups, c_io, clock_data.leave)?;
+    for m in &messages {
+        info(machine, m);
     }
 
-    if let Some(grp) = cfg.grab().arg("group").done(){
-        println!("Filtering by group {}",grp); 
-        let group = clock_data.groups.get(&grp).ok_or(TokErr::Mess(format!("Group not defined \"{}\"",grp) ))?;
-        c_io.retain(|(ind,_)| group.contains(&ind.job));
+    if let Some(sc) = clap.subcommand_matches("balance") {
+        let time = |k: &str| -> Result<Option<STime>, TokErr> {
+            match cfg.grab().conf(format!("config.{}", k)).done() {
+                Some(s) => Ok(Some(s.parse()?)),
+                None => Ok(None),
+            }
+        };
+        let mut overrides = [None; 7];
+        for (i, k) in balance::DAY_KEYS.iter().enumerate() {
+            overrides[i] = time(&format!("target_{}", k))?;
+        }
+        let mut days_off = Vec::new();
+        if let Some(h) = cfg.grab().conf("config.holidays").done() {
+            for d in h.split(',').filter(|d| !d.trim().is_empty()) {
+                days_off.push(clockin::read_date(d.trim())?);
+            }
+        }
+        let targets = balance::Targets::new(time("target_day")?, time("target_week")?, overrides, days_off)?;
+        let start = match cfg.grab().arg("since").done() {
+            Some(d) => clockin::read_date(&d)?,
+            None => c_io.iter().map(|s| s.0.date).chain(leave.iter().map(|l| l.date)).min().unwrap_or(today),
+        };
+        let end = match cfg.grab().arg("until").done() {
+            Some(d) => clockin::read_date(&d)?,
+            None => c_io.iter().map(|s| s.0.date).chain(leave.iter().map(|l| l.date)).max().unwrap_or(today).max(today),
+        };
+        print!("{}", balance::render(&c_io, &targets, &leave, start, end, sc.is_present("weekly"), format));
+        return Ok(());
     }
 
-    if let Some(wks) = cfg.grab().arg("week").done() {
-        let dt = Local::today();
-        let wk = wks.parse::<u32>()?;
-        //.map_err(|_| "Could not parse week value")?;
-        let st = NaiveDate::from_isoywd(dt.year(), wk, Weekday::Mon);
-        let fin = NaiveDate::from_isoywd(dt.year(), wk, Weekday::Sun);
-        println!("Filtering by week {}", wk);
-        c_io.retain(|(ind, _)| ind.date >= st && ind.date <= fin);
+    if let Some(sc) = clap.subcommand_matches("export") {
+        if !sc.is_present("open") {
+            c_io.retain(|(ind, _)| Some(ind) != open.as_ref());
+        }
+        let res = match sc.value_of("to").unwrap_or("") {
+            "ics" => ics::export(&c_io, &all_sessions, open.as_ref(), Local::now().naive_local()),
+            "timeclock" => timeclock::export(&c_io, open.as_ref()),
+            t => return Err(TokErr::Mess(format!("Cannot export to \"{}\", use ics or timeclock", t)).into()),
+        };
+        match sc.value_of("output") {
+            Some(o) => std::fs::write(o, res)?,
+            None => print!("{}", res),
+        }
+        return Ok(());
     }
 
-    //local closure for month filter
-    let month_s_fin = |yr, m| {
-        (
-            NaiveDate::from_ymd(yr, m, 1),
-            match m {
-                12 => NaiveDate::from_ymd(yr + 1, 1, 1),
-                _ => NaiveDate::from_ymd(yr, m + 1, 1),
-            },
-        )
-    };
+    if let Some(sc) = clap.subcommand_matches("calendar") {
+        print!("{}", calendar::render(&c_io, sc.is_present("year"), sc.is_present("hours")));
+        return Ok(());
+    }
 
-    if cfg.bool_flag("this_month", Filter::Arg) {
-        let dt = Local::today();
-        let (st, fin) = month_s_fin(dt.year(), dt.month());
-        c_io.retain(|(ind, _)| ind.date >= st && ind.date < fin);
+    if let Some(sc) = clap.subcommand_matches("chart") {
+        let by = match sc.value_of("by") {
+            Some(b) => b.parse()?,
+            None => breakdown::By::Job,
+        };
+        let width = match sc.value_of("width") {
+            Some(w) => w.parse()?,
+            None => term::term_size().1,
+        };
+        print!("{}", chart::render(&c_io, by, &clock_data.groups, width));
+        return Ok(());
     }
 
-    if let Some(mth) = cfg.grab().arg("month").done() {
-        let dt = Local::today();
-        let (st, fin) = month_s_fin(dt.year(), mth.parse()?);
-        c_io.retain(|(ind, _)| ind.date >= st && ind.date < fin);
+    if clap.subcommand_matches("leave").is_some() {
+        print!("{}", leave::render(&leave, format));
+        return Ok(());
     }
 
-    //TODO filter by given date
-    if cfg.bool_flag("today", Filter::Arg) {
-        let dt = Local::today().naive_local();
-        println!("Filtering by Today");
-        c_io.retain(|(ind, _)| ind.date == dt);
+    if clap.subcommand_matches("tree").is_some() {
+        print!("{}", tree::render(&c_io, format));
+        return Ok(());
     }
 
-    if let Some(d) = cfg.grab().arg("since").done() {
-        let dt = clockin::read_date(&d)?;
-        c_io.retain(|(ind, _)| ind.date >= dt);
+    if clap.subcommand_matches("groups").is_some() {
+        let dims = [breakdown::By::Group, breakdown::By::Job];
+        let root = breakdown::breakdown(&c_io, &dims, &clock_data.groups);
+        print!("{}", breakdown::table(&root, &dims).render(format));
+        return Ok(());
     }
 
-    if let Some(d) = cfg.grab().arg("until").done() {
-        let dt = clockin::read_date(&d)?;
-        c_io.retain(|(ind, _)| ind.date <= dt);
+    if clap.subcommand_matches("tags").is_some() {
+        print!("{}", breakdown::tag_report(&c_io, format));
+        return Ok(());
     }
 
-    if let Some(jb) = cfg.grab().arg("job").done() {
-        c_io.retain(|(ind, _)| ind.job == jb);
+    if clap.subcommand_matches("timesheet").is_some() {
+        print!("{}", timesheet::render(&c_io, format));
+        return Ok(());
     }
 
-    if let Some(jbs) = cfg.grab().arg("jobstart").done() {
-        c_io.retain(|(ind, _)| ind.job.starts_with(&jbs));
+    if let Some(by) = cfg.grab().arg("by").done() {
+        let dims = by.split(',').map(|b|b.parse()).collect::<Result<Vec<breakdown::By>,_>>()?;
+        let root = breakdown::breakdown(&c_io, &dims, &clock_data.groups);
+        print!("{}", breakdown::table(&root, &dims).render(format));
+        return Ok(());
     }
 
-    if let Some(tg) = cfg.grab().arg("tag").done() {
-        c_io.retain(|(ind, _)| ind.tags.contains(&tg.to_string()));
+    let mut rates = rate::Rates::default();
+    if let Some(r) = cfg.grab().arg("rates").conf("config.rates").done() {
+        rates.add(&r)?;
     }
 
-    //build report
-    let mut r_times: BTreeMap<String, STime> = BTreeMap::new();
-    let mut t_time = STime::new(0, 0);
-    let mut last_dat = NaiveDate::from_ymd(1, 1, 1);
-    for (idat, otime) in c_io {
-        let tt = r_times
-            .get(&idat.job)
-            .map(|x| *x)
-            .unwrap_or(STime::new(0, 0));
-        t_time += otime - idat.time;
-        if cfg.bool_flag("print", Filter::Arg) {
-            //maybe move out later
-            if last_dat != idat.date {
-                println!("{}", idat.date.format("%d/%m/%Y"));
-                last_dat = idat.date;
-            }
-            println!(
-                "  {}: {}-{} = {}   => {}",
-                idat.job,
-                idat.time,
-                otime,
-                otime - idat.time,
-                t_time
-            );
-        }
-        r_times.insert(idat.job, tt + otime - idat.time);
+    if let Some(name) = cfg.grab().arg("template").done() {
+        let dir = cfg
+            .grab()
+            .conf("config.templates")
+            .def("{HOME}/.config/work_tock/templates");
+        let dir = clap_conf::replace::replace_env(&dir).map_err(|e| TokErr::Mess(format!("{:?}", e)))?;
+        let path = template::find(std::path::Path::new(&dir), &name)?;
+        let t = template::Template::parse(&std::fs::read_to_string(&path)?)
+            .map_err(|e| TokErr::Mess(format!("{}: {}", path.display(), e)))?;
+        print!("{}", t.render(&template::context(&c_io, &clock_data.groups, &rates))?);
+        return Ok(());
     }
 
-    println!("\n{:?}\n", r_times);
-    println!("Total Time = {}", t_time);
+    if format == Format::Html {
+        print!("{}", html::render(&c_io, &rates, &title));
+        return Ok(());
+    }
+
+    if format == Format::Markdown {
+        print!("{}", markdown::render(&c_io));
+        return Ok(());
+    }
 
+    if format == Format::Csv {
+        print!("{}", report::summary(&c_io, &rates).render(format));
+        return Ok(());
+    }
+
+    print!("{}", default_report(c_io, &leave, cfg.bool_flag("print", Filter::Arg)));
     Ok(())
 }
 
-fn do_quotes(s:&str)->String{
-    for c in " \n\t".chars(){
-        if s.contains(c){
-            return format!("\"{}\"",s);
-        }
-    }
-    s.to_string()
+/// Job names must follow the case style required by flag or config
+fn check_case<'a, G: Getter<'a, String>>(cfg: &'a G, job: &str) -> Result<(), TokErr> {
+    let f_camel = cfg.bool_flag("camel",Filter::Arg) || cfg.bool_flag("config.camel",Filter::Conf);
+    let f_snake = cfg.bool_flag("snake",Filter::Arg) || cfg.bool_flag("config.snake",Filter::Conf);
+    punch::check_case(job, f_camel, f_snake)
 }
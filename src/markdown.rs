@@ -0,0 +1,60 @@
+//! The sessions of each day and the totals per job, to paste into a wiki or PR
+
+use crate::clockin::Session;
+use crate::rate::Rates;
+use crate::report::{duration, summary, Table};
+use crate::s_time::STime;
+use chrono::naive::NaiveDate;
+use std::collections::BTreeMap;
+
+/// One table per day mirroring "-p", then the totals per job
+pub fn render(sessions: &[Session]) -> String {
+    let mut days: BTreeMap<NaiveDate, Vec<&Session>> = BTreeMap::new();
+    for s in sessions {
+        days.entry(s.0.date).or_default().push(s);
+    }
+    let mut res = String::new();
+    for (d, day) in days {
+        let mut tab = Table::new(&["Job", "Start", "End", "Duration", "Note"]);
+        let mut total = STime::default();
+        for s in day {
+            total += duration(s);
+            tab.rows.push(vec![
+                s.0.job.clone(),
+                s.0.time.to_string(),
+                s.1.to_string(),
+                duration(s).to_string(),
+                s.0.note.clone().unwrap_or_default(),
+            ]);
+        }
+        let mut foot = vec![String::new(); 5];
+        foot[0] = "Total".to_string();
+        foot[3] = total.to_string();
+        tab.foot.push(foot);
+        res.push_str(&format!(
+            "## {}\n\n{}\n",
+            d.format("%d/%m/%Y"),
+            tab.markdown()
+        ));
+    }
+    res.push_str(&format!(
+        "## Summary\n\n{}",
+        summary(sessions, &Rates::default()).markdown()
+    ));
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    pub fn markdown_has_a_section_per_day() {
+        let md = render(&fixture::sessions(fixture::DEMO));
+        assert!(md.contains("## 23/01/2019\n\n| Job | Start | End | Duration | Note |\n"));
+        assert!(md.contains("## 24/01/2019\n"));
+        assert!(md.contains("## Summary\n\n| Job | Sessions | Time |\n"));
+        assert!(md.ends_with("| **Total** | **6** | **07:20** |\n"));
+    }
+}
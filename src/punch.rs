@@ -0,0 +1,126 @@
+//! The checks and lines for clocking in and out, shared by the command line and the server
+
+use crate::clockin::InData;
+use crate::err::TokErr;
+use crate::gob;
+use crate::s_time::STime;
+use chrono::naive::NaiveDate;
+
+/// Jobs must follow the case style asked for in the flags or config
+pub fn check_case(job: &str, camel: bool, snake: bool) -> Result<(), TokErr> {
+    if camel && job.contains('_') {
+        return Err(TokErr::from("You have required CamelCase job entires"));
+    }
+    if snake && job.chars().any(|c| c.is_uppercase()) {
+        return Err(TokErr::from("You have required snake_case job entires"));
+    }
+    Ok(())
+}
+
+/// The end time for clocking out of the open session
+pub fn clock_out(
+    c_data: &InData,
+    today: NaiveDate,
+    now: STime,
+    long_day: bool,
+) -> Result<STime, TokErr> {
+    if today > c_data.date && !long_day {
+        return Err(TokErr::from(format!(
+            "Last Clockin was not today: {}. Please use -l to confirm long day",
+            c_data
+        )));
+    }
+    let since = now.since(&today, c_data.time, &c_data.date);
+    if since < STime::new(0, 0) {
+        return Err(TokErr::from("Cannot clockout before clockin"));
+    }
+    Ok(since + c_data.time)
+}
+
+pub fn out_line(otime: STime) -> String {
+    format!("  -{}", otime)
+}
+
+/// How long the open session has run, when a new clockin ends it
+pub fn switch_out(c_data: &InData, today: NaiveDate, now: STime) -> Result<STime, TokErr> {
+    if c_data.date != today {
+        return Err(TokErr::from("You are currently clocked in from a different date, Please clockout from that before clocking in."));
+    }
+    let since = now.since(&today, c_data.time, &c_data.date);
+    if since < STime::new(0, 0) {
+        return Err(TokErr::from("You are currently clocked in since after the given time. Cannot clockout before clocking in"));
+    }
+    Ok(since)
+}
+
+/// The new session, keeping the tags of the last one unless set
+pub fn new_session(
+    last: Option<&InData>,
+    job: &str,
+    set_tags: Option<&[String]>,
+    today: NaiveDate,
+    now: STime,
+) -> InData {
+    InData {
+        time: now,
+        job: job.to_string(),
+        tags: set_tags
+            .map(|t| t.to_vec())
+            .or_else(|| last.map(|lj| lj.tags.clone()))
+            .unwrap_or_default(),
+        date: today,
+        line: 0,
+        note: None,
+    }
+}
+
+/// The line to append for a clockin, after a date line if the day has changed,
+/// and only naming the job if it is not the last one
+pub fn in_line(
+    last: Option<&InData>,
+    job: &str,
+    marker: Option<&str>,
+    set_tags: Option<&[String]>,
+    today: NaiveDate,
+    now: STime,
+) -> String {
+    let mut f_line = match last {
+        Some(lj) if lj.date == today => "\t".to_string(),
+        _ => today.format("%d/%m/%Y\n\t").to_string(),
+    };
+    let mut items: Vec<String> = marker.iter().map(|m| m.to_string()).collect();
+    if last.map(|lj| lj.job != job) != Some(false) {
+        items.push(gob::do_quotes(job));
+    }
+    if let Some(t) = set_tags {
+        items.extend(gob::tag_items(t));
+    }
+    items.push(now.to_string());
+    f_line.push_str(&items.join(","));
+    f_line
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn in_line_only_says_what_changed() {
+        let d = NaiveDate::from_ymd(2020, 3, 4);
+        let last = new_session(None, "car_wash", None, d, STime::new(9, 0));
+        let t = STime::new(10, 30);
+        assert_eq!(
+            in_line(Some(&last), "car_wash", None, None, d, t),
+            "\t10:30"
+        );
+        assert_eq!(in_line(Some(&last), "eat", None, None, d, t), "\teat,10:30");
+        let tags = vec!["x".to_string()];
+        assert_eq!(
+            in_line(None, "eat", Some(">>"), Some(&tags), d, t),
+            "04/03/2020\n\t>>,eat,__x,10:30"
+        );
+        assert!(clock_out(&last, d, STime::new(8, 0), false).is_err());
+        assert_eq!(clock_out(&last, d, t, false), Ok(t));
+        assert!(switch_out(&last, d.succ(), t).is_err());
+    }
+}
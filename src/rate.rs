@@ -0,0 +1,76 @@
+//! Hourly rates for jobs, to put a price on reports
+
+use crate::err::TokErr;
+use crate::gob::JOB_SEP;
+use crate::s_time::STime;
+use crate::tree;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Rates {
+    /// For jobs with no rate of their own
+    pub default: Option<f64>,
+    pub jobs: BTreeMap<String, f64>,
+}
+
+impl Rates {
+    /// Reads "50" or "client_a=80,client_b/site=65", a bare number being the default
+    pub fn add(&mut self, spec: &str) -> Result<(), TokErr> {
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (job, r) = match part.find('=') {
+                Some(n) => (Some(part[..n].trim()), &part[n + 1..]),
+                None => (None, part),
+            };
+            let r: f64 = r
+                .trim()
+                .parse()
+                .map_err(|_| TokErr::Mess(format!("Could not read rate \"{}\"", part)))?;
+            match job {
+                Some(j) => {
+                    self.jobs.insert(j.to_string(), r);
+                }
+                None => self.default = Some(r),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.jobs.is_empty()
+    }
+
+    /// The rate of the job, or of the nearest job above it, or the default
+    pub fn for_job(&self, job: &str) -> Option<f64> {
+        let lv = tree::levels(job);
+        (1..=lv.len())
+            .rev()
+            .find_map(|n| self.jobs.get(&lv[..n].join(&JOB_SEP.to_string())))
+            .copied()
+            .or(self.default)
+    }
+}
+
+/// The price of some time at an hourly rate
+pub fn amount(t: STime, rate: f64) -> f64 {
+    t.as_mins() as f64 * rate / 60.
+}
+
+pub fn money(m: f64) -> String {
+    format!("{:.2}", m)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn rates_fall_back_to_parents() {
+        let mut r = Rates::default();
+        r.add("40, client=80,client/support=60").unwrap();
+        assert_eq!(r.for_job("client/support/phone"), Some(60.));
+        assert_eq!(r.for_job("client/backend"), Some(80.));
+        assert_eq!(r.for_job("home"), Some(40.));
+        assert_eq!(money(amount(STime::new(1, 30), 60.)), "90.00");
+        assert!(r.add("client=lots").is_err());
+    }
+}
@@ -0,0 +1,336 @@
+//! Shared pieces for building reports from the filtered sessions
+
+use crate::clockin::Session;
+use crate::err::TokErr;
+use crate::rate::{self, Rates};
+use crate::s_time::STime;
+use chrono::naive::NaiveDate;
+use chrono::{Datelike, Weekday};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Csv,
+    Markdown,
+    Html,
+}
+
+impl FromStr for Format {
+    type Err = TokErr;
+    fn from_str(s: &str) -> Result<Self, TokErr> {
+        match s.to_lowercase().as_ref() {
+            "text" | "txt" => Ok(Format::Text),
+            "csv" => Ok(Format::Csv),
+            "markdown" | "md" => Ok(Format::Markdown),
+            "html" | "htm" => Ok(Format::Html),
+            _ => Err(TokErr::Mess(format!("Unknown format \"{}\"", s))),
+        }
+    }
+}
+
+/// The length of a session
+pub fn duration(s: &Session) -> STime {
+    s.1 - s.0.time
+}
+
+/// The Monday and Sunday of the ISO week containing d
+pub fn week_of(d: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let w = d.iso_week();
+    (
+        NaiveDate::from_isoywd(w.year(), w.week(), Weekday::Mon),
+        NaiveDate::from_isoywd(w.year(), w.week(), Weekday::Sun),
+    )
+}
+
+/// Whether a session's tags pass the filter, needing all of want, or any of it
+pub fn tags_match(have: &[String], want: &[String], any: bool) -> bool {
+    match any {
+        true => want.iter().any(|t| have.contains(t)),
+        false => want.iter().all(|t| have.contains(t)),
+    }
+}
+
+/// A difference in time, always with its sign
+pub fn signed(t: STime) -> String {
+    match t.as_mins() {
+        m if m < 0 => format!("-{}", STime::new(0, -m)),
+        _ => format!("+{}", t),
+    }
+}
+
+pub fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A whole html document with its own styles, so it can be sent as a single file
+pub fn html_page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n\
+         <style>\n{1}</style>\n</head>\n<body>\n<h1>{0}</h1>\n{2}</body>\n</html>\n",
+        html_escape(title),
+        PAGE_CSS,
+        body
+    )
+}
+
+const PAGE_CSS: &str = "body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.5em; }
+h2, h3 { font-size: 1.15em; margin-top: 1.5em; }
+table { border-collapse: collapse; margin: 0.5em 0 1em 0; }
+th, td { padding: 0.25em 0.8em; border-bottom: 1px solid #ddd; }
+th { background: #f3f3f3; text-align: left; }
+td.num, th.num { text-align: right; font-variant-numeric: tabular-nums; }
+tfoot td { font-weight: bold; border-top: 2px solid #999; }
+";
+
+/// Time shown in a table cell, blank if nothing was done
+pub fn cell(t: STime) -> String {
+    if t == STime::new(0, 0) {
+        String::new()
+    } else {
+        t.to_string()
+    }
+}
+
+/// Rows of text with a header, and footer rows (eg totals) below a rule
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Table {
+    pub head: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    pub foot: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new<S: ToString>(head: &[S]) -> Self {
+        Table {
+            head: head.iter().map(|s| s.to_string()).collect(),
+            rows: Vec::new(),
+            foot: Vec::new(),
+        }
+    }
+
+    pub fn render(&self, f: Format) -> String {
+        match f {
+            Format::Text => self.text(),
+            Format::Csv => self.csv(),
+            Format::Markdown => self.markdown(),
+            Format::Html => self.html(),
+        }
+    }
+
+    fn all_rows(&self) -> impl Iterator<Item = &Vec<String>> {
+        std::iter::once(&self.head)
+            .chain(self.rows.iter())
+            .chain(self.foot.iter())
+    }
+
+    fn widths(&self) -> Vec<usize> {
+        let mut res = vec![0; self.head.len()];
+        for r in self.all_rows() {
+            for (i, c) in r.iter().enumerate() {
+                if i >= res.len() {
+                    res.push(0);
+                }
+                res[i] = res[i].max(c.chars().count());
+            }
+        }
+        res
+    }
+
+    /// Aligned columns, the first to the left and the rest to the right
+    pub fn text(&self) -> String {
+        let w = self.widths();
+        let line = |r: &Vec<String>| {
+            let cells: Vec<String> = r
+                .iter()
+                .enumerate()
+                .map(|(i, c)| match i {
+                    0 => format!("{:<1$}", c, w[0]),
+                    _ => format!("{:>1$}", c, w[i]),
+                })
+                .collect();
+            format!("{}\n", cells.join("  ").trim_end())
+        };
+        let rule = format!(
+            "{}\n",
+            "-".repeat(w.iter().sum::<usize>() + 2 * (w.len() - 1))
+        );
+        let mut res = line(&self.head);
+        res.push_str(&rule);
+        for r in &self.rows {
+            res.push_str(&line(r));
+        }
+        if !self.foot.is_empty() {
+            res.push_str(&rule);
+            for r in &self.foot {
+                res.push_str(&line(r));
+            }
+        }
+        res
+    }
+
+    pub fn csv(&self) -> String {
+        let field = |c: &String| {
+            if c.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", c.replace('"', "\"\""))
+            } else {
+                c.clone()
+            }
+        };
+        self.all_rows()
+            .map(|r| format!("{}\n", r.iter().map(field).collect::<Vec<_>>().join(",")))
+            .collect()
+    }
+
+    /// For html and markdown, the first column to the left and the rest to the right,
+    /// unless they hold text such as notes, or nothing at all
+    fn right_aligned(&self) -> Vec<bool> {
+        let num = |c: &String| {
+            c.chars()
+                .all(|ch| ch.is_ascii_digit() || ":.+-%".contains(ch))
+        };
+        (0..self.head.len())
+            .map(|i| {
+                let cells: Vec<&String> = self
+                    .rows
+                    .iter()
+                    .filter_map(|r| r.get(i))
+                    .filter(|c| !c.is_empty())
+                    .collect();
+                i > 0 && !cells.is_empty() && cells.into_iter().all(num)
+            })
+            .collect()
+    }
+
+    pub fn html(&self) -> String {
+        let right = self.right_aligned();
+        let row = |r: &Vec<String>, tag: &str| {
+            let cells: String = r
+                .iter()
+                .enumerate()
+                .map(|(i, c)| {
+                    let class = match right.get(i) {
+                        Some(true) => " class=\"num\"",
+                        _ => "",
+                    };
+                    format!("<{0}{1}>{2}</{0}>", tag, class, html_escape(c))
+                })
+                .collect();
+            format!("<tr>{}</tr>\n", cells)
+        };
+        let mut res = format!(
+            "<table>\n<thead>\n{}</thead>\n<tbody>\n",
+            row(&self.head, "th")
+        );
+        for r in &self.rows {
+            res.push_str(&row(r, "td"));
+        }
+        res.push_str("</tbody>\n");
+        if !self.foot.is_empty() {
+            res.push_str("<tfoot>\n");
+            for r in &self.foot {
+                res.push_str(&row(r, "td"));
+            }
+            res.push_str("</tfoot>\n");
+        }
+        res.push_str("</table>\n");
+        res
+    }
+
+    pub fn markdown(&self) -> String {
+        let line = |r: &Vec<String>| {
+            let cells: Vec<String> = r.iter().map(|c| c.replace('|', "\\|")).collect();
+            format!("| {} |\n", cells.join(" | "))
+        };
+        let mut res = line(&self.head);
+        let align: Vec<&str> = self
+            .right_aligned()
+            .into_iter()
+            .map(|r| if r { "---:" } else { "---" })
+            .collect();
+        res.push_str(&format!("|{}|\n", align.join("|")));
+        for r in self.rows.iter() {
+            res.push_str(&line(r));
+        }
+        for r in self.foot.iter() {
+            let bold: Vec<String> = r
+                .iter()
+                .map(|c| {
+                    if c.is_empty() {
+                        c.clone()
+                    } else {
+                        format!("**{}**", c)
+                    }
+                })
+                .collect();
+            res.push_str(&line(&bold));
+        }
+        res
+    }
+}
+
+/// Sessions and time per job, with the rate and amount when there are rates
+pub fn summary(sessions: &[Session], rates: &Rates) -> Table {
+    let mut jobs: BTreeMap<&str, (usize, STime)> = BTreeMap::new();
+    for s in sessions {
+        let e = jobs.entry(&s.0.job).or_default();
+        e.0 += 1;
+        e.1 += duration(s);
+    }
+    let priced = !rates.is_empty();
+    let mut head = vec!["Job", "Sessions", "Time"];
+    if priced {
+        head.extend(&["Rate", "Amount"]);
+    }
+    let mut tab = Table::new(&head);
+    let (mut count, mut time, mut total) = (0, STime::default(), 0.);
+    for (job, (n, t)) in jobs {
+        let mut row = vec![job.to_string(), n.to_string(), t.to_string()];
+        if priced {
+            let r = rates.for_job(job);
+            let m = r.map(|r| rate::amount(t, r));
+            total += m.unwrap_or(0.);
+            row.push(r.map(rate::money).unwrap_or_default());
+            row.push(m.map(rate::money).unwrap_or_default());
+        }
+        count += n;
+        time += t;
+        tab.rows.push(row);
+    }
+    let mut foot = vec!["Total".to_string(), count.to_string(), time.to_string()];
+    if priced {
+        foot.extend(vec![String::new(), rate::money(total)]);
+    }
+    tab.foot.push(foot);
+    tab
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn tags_match_all_or_any() {
+        let have = vec!["a".to_string(), "b".to_string()];
+        let want = vec!["a".to_string(), "c".to_string()];
+        assert!(!tags_match(&have, &want, false));
+        assert!(tags_match(&have, &want, true));
+        assert!(tags_match(&have, &want[..1], false));
+    }
+
+    #[test]
+    pub fn empty_columns_stay_left() {
+        let mut tab = Table::new(&["Job", "Time", "Rate", "Note"]);
+        tab.rows
+            .push(vec!["a".into(), "01:00".into(), "".into(), "".into()]);
+        tab.rows
+            .push(vec!["b".into(), "02:30".into(), "10.00".into(), "".into()]);
+        assert_eq!(tab.right_aligned(), vec![false, true, true, false]);
+        assert!(tab.markdown().contains("|---|---:|---:|---|\n"));
+    }
+}
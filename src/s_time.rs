@@ -11,7 +11,7 @@ use std::str::FromStr;
 use crate::err::TokErr;
 //use crate::pesto::{LineNum, Pestable, Rule, TimeFile};
 
-#[derive(Copy, Clone, PartialOrd, PartialEq, Eq, Add, Sub, AddAssign, SubAssign)]
+#[derive(Copy, Clone, Default, PartialOrd, Ord, PartialEq, Eq, Add, Sub, AddAssign, SubAssign)]
 pub struct STime(isize); //minutes
 
 impl STime {
@@ -23,6 +23,10 @@ impl STime {
         STime::new(t.time().hour() as isize, t.time().minute() as isize)
     }
 
+    pub fn as_mins(&self) -> isize {
+        self.0
+    }
+
     pub fn since(&self, now_date: &NaiveDate, then_time: Self, then_date: &NaiveDate) -> Self {
         let days_between = (*now_date - *then_date).num_days() as isize;
         *self + STime::new(24 * days_between, 0) - then_time
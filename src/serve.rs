@@ -0,0 +1,412 @@
+//! The pieces of the local http server: reading requests, writing json, and the dashboard page
+
+use crate::clockin::{self, InData, Session};
+use crate::err::TokErr;
+use crate::group::{self, Groups};
+use crate::report::{self, duration, week_of};
+use crate::s_time::STime;
+use crate::tree;
+use chrono::naive::NaiveDate;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+/// Requests bigger than this are refused
+pub const MAX_BODY: usize = 64 * 1024;
+
+/// How long a connection may take to send its request or read the reply
+pub const TIMEOUT_SECS: u64 = 5;
+
+/// Sent by the dashboard with every write, a browser will not let another site send it without asking
+pub const WRITE_HEADER: &str = "x-work-tock";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Header names are lower case
+    pub headers: BTreeMap<String, String>,
+    /// From the query string, then the form encoded body
+    pub params: BTreeMap<String, String>,
+}
+
+impl Request {
+    pub fn read<R: BufRead>(r: &mut R) -> Result<Self, TokErr> {
+        let mut line = String::new();
+        r.read_line(&mut line)
+            .map_err(|e| TokErr::Mess(e.to_string()))?;
+        let mut parts = line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next()) {
+            (Some(m), Some(t)) => (m.to_string(), t),
+            _ => return Err(TokErr::from("Bad request line")),
+        };
+        let (path, query) = match target.find('?') {
+            Some(n) => (&target[..n], &target[n + 1..]),
+            None => (target, ""),
+        };
+        let mut req = Request {
+            method,
+            path: url_decode(path),
+            headers: BTreeMap::new(),
+            params: parse_query(query),
+        };
+        loop {
+            let mut h = String::new();
+            r.read_line(&mut h)
+                .map_err(|e| TokErr::Mess(e.to_string()))?;
+            let h = h.trim_end();
+            if h.is_empty() {
+                break;
+            }
+            if let Some(n) = h.find(':') {
+                req.headers
+                    .insert(h[..n].trim().to_lowercase(), h[n + 1..].trim().to_string());
+            }
+        }
+        let len: usize = match req.headers.get("content-length") {
+            Some(l) => l.parse()?,
+            None => 0,
+        };
+        if len > MAX_BODY {
+            return Err(TokErr::from("Request too large"));
+        }
+        let mut body = vec![0; len];
+        r.read_exact(&mut body)
+            .map_err(|e| TokErr::Mess(e.to_string()))?;
+        for (k, v) in parse_query(&String::from_utf8_lossy(&body)) {
+            req.params.entry(k).or_insert(v);
+        }
+        Ok(req)
+    }
+
+    /// Only pages asked for by name from this machine, so other sites cannot reach in through the browser
+    pub fn is_local(&self) -> bool {
+        let host = self.headers.get("host").map(|h| h.as_str()).unwrap_or("");
+        let name = match host.rfind(':') {
+            Some(n) if !host.ends_with(']') => &host[..n],
+            _ => host,
+        };
+        ["localhost", "127.0.0.1", "[::1]"].contains(&name)
+    }
+}
+
+pub fn url_decode(s: &str) -> String {
+    let b = s.as_bytes();
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i < b.len() {
+        match b[i] {
+            b'+' => res.push(b' '),
+            b'%' if i + 2 < b.len() => {
+                match std::str::from_utf8(&b[i + 1..i + 3])
+                    .ok()
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                {
+                    Some(c) => {
+                        res.push(c);
+                        i += 2;
+                    }
+                    None => res.push(b'%'),
+                }
+            }
+            c => res.push(c),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&res).to_string()
+}
+
+pub fn parse_query(s: &str) -> BTreeMap<String, String> {
+    s.split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| match p.find('=') {
+            Some(n) => (url_decode(&p[..n]), url_decode(&p[n + 1..])),
+            None => (url_decode(p), String::new()),
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(body: String) -> Self {
+        Response {
+            status: 200,
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    pub fn html(body: &str) -> Self {
+        Response {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            body: body.to_string(),
+        }
+    }
+
+    pub fn error(status: u16, msg: &str) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: format!("{{\"error\":{}}}", json_str(msg)),
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            _ => "Error",
+        };
+        write!(
+            w,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len(),
+            self.body
+        )?;
+        w.flush()
+    }
+}
+
+pub fn json_str(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+fn json_list<I: IntoIterator<Item = String>>(items: I) -> String {
+    format!("[{}]", items.into_iter().collect::<Vec<_>>().join(","))
+}
+
+fn date_str(d: NaiveDate) -> String {
+    json_str(&d.format("%d/%m/%Y").to_string())
+}
+
+pub fn session_json(s: &Session) -> String {
+    format!(
+        "{{\"date\":{},\"job\":{},\"tags\":{},\"start\":{},\"end\":{},\"time\":{},\"note\":{}}}",
+        date_str(s.0.date),
+        json_str(&s.0.job),
+        json_list(s.0.tags.iter().map(|t| json_str(t))),
+        json_str(&s.0.time.to_string()),
+        json_str(&s.1.to_string()),
+        json_str(&duration(s).to_string()),
+        json_str(s.0.note.as_deref().unwrap_or("")),
+    )
+}
+
+pub fn sessions_json(sessions: &[Session]) -> String {
+    json_list(sessions.iter().map(session_json))
+}
+
+/// The total, and the time per job
+pub fn totals_json(sessions: &[Session]) -> String {
+    let mut jobs: BTreeMap<&str, STime> = BTreeMap::new();
+    for s in sessions {
+        *jobs.entry(&s.0.job).or_default() += duration(s);
+    }
+    let total = jobs.values().fold(STime::default(), |a, b| a + *b);
+    format!(
+        "{{\"total\":{},\"jobs\":{{{}}}}}",
+        json_str(&total.to_string()),
+        jobs.iter()
+            .map(|(j, t)| format!("{}:{}", json_str(j), json_str(&t.to_string())))
+            .collect::<Vec<_>>()
+            .join(",")
+    )
+}
+
+/// Whether clocked in and to what, with the time done today and this week.
+/// The sessions include the open one, ending now.
+pub fn status_json(
+    sessions: &[Session],
+    open: Option<&InData>,
+    today: NaiveDate,
+    messages: &[String],
+) -> String {
+    let (st, fin) = week_of(today);
+    let sum = |f: &dyn Fn(NaiveDate) -> bool| {
+        sessions
+            .iter()
+            .filter(|s| f(s.0.date))
+            .fold(STime::default(), |a, s| a + duration(s))
+    };
+    let mut recent: Vec<&str> = Vec::new();
+    for s in sessions.iter().rev() {
+        if !recent.contains(&s.0.job.as_str()) && recent.len() < 10 {
+            recent.push(&s.0.job);
+        }
+    }
+    let current = match open.and_then(|o| sessions.iter().rev().find(|s| &s.0 == o)) {
+        Some(s) => session_json(s),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"clocked_in\":{},\"current\":{},\"date\":{},\"today\":{},\"week\":{},\"recent\":{},\"messages\":{}}}",
+        open.is_some(),
+        current,
+        date_str(today),
+        json_str(&sum(&|d| d == today).to_string()),
+        json_str(&sum(&|d| d >= st && d <= fin).to_string()),
+        json_list(recent.into_iter().map(json_str)),
+        json_list(messages.iter().map(|m| json_str(m))),
+    )
+}
+
+/// The same filters as the command line: since, until, job, under, tag and group
+pub fn filter(
+    mut sessions: Vec<Session>,
+    params: &BTreeMap<String, String>,
+    groups: &Groups,
+) -> Result<Vec<Session>, TokErr> {
+    for (k, v) in params {
+        match k.as_ref() {
+            "since" => {
+                let d = clockin::read_date(v)?;
+                sessions.retain(|s| s.0.date >= d);
+            }
+            "until" => {
+                let d = clockin::read_date(v)?;
+                sessions.retain(|s| s.0.date <= d);
+            }
+            "job" => sessions.retain(|s| &s.0.job == v),
+            "under" => sessions.retain(|s| tree::in_subtree(&s.0.job, v)),
+            "tag" => {
+                let tags: Vec<String> = v.split(',').map(|t| t.trim().to_string()).collect();
+                sessions.retain(|s| report::tags_match(&s.0.tags, &tags, false));
+            }
+            "group" => {
+                groups
+                    .get(v)
+                    .ok_or_else(|| TokErr::Mess(format!("Group not defined \"{}\"", v)))?;
+                sessions.retain(|s| group::in_group(groups, v, &s.0));
+            }
+            _ => {}
+        }
+    }
+    Ok(sessions)
+}
+
+pub const DASHBOARD: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Work Tock</title>
+<style>
+body { font-family: sans-serif; margin: 2em; color: #222; max-width: 50em; }
+#status { font-size: 1.3em; margin-bottom: 1em; }
+#error { color: #b00; }
+#messages { color: #850; white-space: pre-line; }
+input, button { font-size: 1em; padding: 0.3em 0.6em; margin: 0.2em; }
+.recent button { background: #eef; border: 1px solid #99c; border-radius: 3px; }
+table { border-collapse: collapse; margin-top: 1em; }
+th, td { padding: 0.25em 0.8em; border-bottom: 1px solid #ddd; text-align: left; }
+</style>
+</head>
+<body>
+<h1>Work Tock</h1>
+<div id="status">Loading...</div>
+<div id="totals"></div>
+<p id="error"></p>
+<p id="messages"></p>
+<form id="clockin">
+<input id="job" placeholder="job" list="jobs" required>
+<input id="tags" placeholder="tags, comma separated">
+<button type="submit">Clock in</button>
+<button type="button" id="out">Clock out</button>
+</form>
+<datalist id="jobs"></datalist>
+<div class="recent" id="recent"></div>
+<h2>Today</h2>
+<table><thead><tr><th>Job</th><th>Start</th><th>End</th><th>Time</th><th>Tags</th></tr></thead>
+<tbody id="sessions"></tbody></table>
+<script>
+const text = s => { const d = document.createElement("div"); d.textContent = s; return d.innerHTML; };
+async function call(method, path, params) {
+  const opts = { method: method, headers: { "X-Work-Tock": "1" } };
+  if (params) { opts.body = new URLSearchParams(params); }
+  const r = await fetch(path, opts);
+  const j = await r.json();
+  document.getElementById("error").textContent = j.error || "";
+  return j;
+}
+const show = j => { document.getElementById("messages").textContent = (j.messages || []).join("\n"); };
+async function refresh() {
+  const s = await call("GET", "/api/status");
+  if (s.error) { return; }
+  document.getElementById("status").innerHTML = s.clocked_in
+    ? "Clocked in to <b>" + text(s.current.job) + "</b> since " + s.current.start + " (" + s.current.time + ")"
+    : "Not clocked in";
+  document.getElementById("totals").textContent = "Today " + s.today + ", this week " + s.week;
+  document.getElementById("jobs").innerHTML = s.recent.map(j => "<option value=\"" + text(j) + "\">").join("");
+  const rec = document.getElementById("recent");
+  rec.innerHTML = "";
+  for (const j of s.recent) {
+    const b = document.createElement("button");
+    b.textContent = j;
+    b.onclick = async () => { show(await call("POST", "/api/in", { job: j })); refresh(); };
+    rec.appendChild(b);
+  }
+  const list = await call("GET", "/api/sessions?since=" + encodeURIComponent(s.date));
+  document.getElementById("sessions").innerHTML = list.map(x =>
+    "<tr><td>" + text(x.job) + "</td><td>" + x.start + "</td><td>" + x.end + "</td><td>" + x.time +
+    "</td><td>" + text(x.tags.join(", ")) + "</td></tr>").join("");
+}
+document.getElementById("clockin").onsubmit = async e => {
+  e.preventDefault();
+  const p = { job: document.getElementById("job").value };
+  const tags = document.getElementById("tags").value;
+  if (tags) { p.tags = tags; }
+  show(await call("POST", "/api/in", p));
+  refresh();
+};
+document.getElementById("out").onclick = async () => { show(await call("POST", "/api/out")); refresh(); };
+refresh();
+setInterval(refresh, 30000);
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn request_reads_query_and_form() {
+        let raw = "POST /api/in?job=car%20wash HTTP/1.1\r\nHost: localhost:8377\r\nContent-Length: 12\r\n\r\ntags=a%2Cb&x";
+        let req = Request::read(&mut raw.as_bytes()).unwrap();
+        assert_eq!(req.method, "POST");
+        assert_eq!(req.path, "/api/in");
+        assert_eq!(req.params["job"], "car wash");
+        assert_eq!(req.params["tags"], "a,b");
+        assert_eq!(req.params["x"], "");
+        assert!(req.is_local());
+        let mut evil = req.clone();
+        evil.headers
+            .insert("host".to_string(), "evil.com:8377".to_string());
+        assert!(!evil.is_local());
+        assert_eq!(json_str("a \"b\"\n"), r#""a \"b\"\n""#);
+    }
+}
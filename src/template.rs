@@ -0,0 +1,455 @@
+//! User written reports, eg: "{{#each jobs}}{{name}}: {{time|hours}}\n{{/each}}"
+//!
+//! "{{path|filter:arg}}" prints a value, "{{#each list}}" and "{{#if value}}{{else}}"
+//! open blocks closed by "{{/each}}" and "{{/if}}".
+
+use crate::clockin::Session;
+use crate::err::TokErr;
+use crate::group::{self, Groups};
+use crate::rate::{self, Rates};
+use crate::report::duration;
+use crate::s_time::STime;
+use chrono::naive::NaiveDate;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Text(String),
+    Num(f64),
+    Time(STime),
+    Date(NaiveDate),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Text(s) => !s.is_empty(),
+            Value::Num(n) => *n != 0.,
+            Value::Time(t) => t.as_mins() != 0,
+            Value::Date(_) => true,
+            Value::List(l) => !l.is_empty(),
+            Value::Map(m) => !m.is_empty(),
+        }
+    }
+
+    fn get(&self, k: &str) -> Option<Value> {
+        match (self, k) {
+            (Value::Map(m), _) => m.get(k).cloned(),
+            (Value::List(l), "len") => Some(Value::Num(l.len() as f64)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Text(s) => write!(f, "{}", s),
+            Value::Num(n) if n.fract() == 0. => write!(f, "{}", n),
+            Value::Num(n) => write!(f, "{:.2}", n),
+            Value::Time(t) => write!(f, "{}", t),
+            Value::Date(d) => write!(f, "{}", d.format("%d/%m/%Y")),
+            Value::List(l) => {
+                let items: Vec<String> = l.iter().map(|v| v.to_string()).collect();
+                write!(f, "{}", items.join(", "))
+            }
+            Value::Map(_) => write!(f, "[map]"),
+        }
+    }
+}
+
+fn map(items: Vec<(&str, Value)>) -> Value {
+    Value::Map(items.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+fn text(s: &str) -> Value {
+    Value::Text(s.to_string())
+}
+
+fn total(sessions: &[&Session]) -> STime {
+    sessions
+        .iter()
+        .fold(STime::default(), |a, s| a + duration(s))
+}
+
+/// The price of the sessions, as a Num, or empty text if any job has no rate
+fn amount(sessions: &[&Session], rates: &Rates) -> Value {
+    let mut res = 0.;
+    for s in sessions {
+        match rates.for_job(&s.0.job) {
+            Some(r) => res += rate::amount(duration(s), r),
+            None => return text(""),
+        }
+    }
+    Value::Num(res)
+}
+
+fn session(s: &Session, rates: &Rates) -> Value {
+    map(vec![
+        ("date", Value::Date(s.0.date)),
+        ("job", text(&s.0.job)),
+        ("start", Value::Time(s.0.time)),
+        ("end", Value::Time(s.1)),
+        ("time", Value::Time(duration(s))),
+        (
+            "tags",
+            Value::List(s.0.tags.iter().map(|t| text(t)).collect()),
+        ),
+        ("note", text(s.0.note.as_deref().unwrap_or(""))),
+        ("amount", amount(&[s], rates)),
+    ])
+}
+
+/// A summary Map for each key, in key order
+fn totals<K: Ord, F: Fn(&K) -> Vec<(&'static str, Value)>>(
+    by: BTreeMap<K, Vec<&Session>>,
+    rates: &Rates,
+    head: F,
+) -> Value {
+    Value::List(
+        by.into_iter()
+            .map(|(k, ss)| {
+                let mut items = head(&k);
+                items.push(("time", Value::Time(total(&ss))));
+                items.push(("count", Value::Num(ss.len() as f64)));
+                items.push(("amount", amount(&ss, rates)));
+                items.push((
+                    "sessions",
+                    Value::List(ss.iter().map(|s| session(s, rates)).collect()),
+                ));
+                map(items)
+            })
+            .collect(),
+    )
+}
+
+/// Everything a template can use, see the readme for the names
+pub fn context(sessions: &[Session], groups: &Groups, rates: &Rates) -> Value {
+    let all: Vec<&Session> = sessions.iter().collect();
+    let mut jobs: BTreeMap<&str, Vec<&Session>> = BTreeMap::new();
+    let mut days: BTreeMap<NaiveDate, Vec<&Session>> = BTreeMap::new();
+    let mut in_groups: BTreeMap<&str, Vec<&Session>> = BTreeMap::new();
+    for s in &all {
+        jobs.entry(&s.0.job).or_default().push(s);
+        days.entry(s.0.date).or_default().push(s);
+        for g in groups.keys() {
+            if group::in_group(groups, g, &s.0) {
+                in_groups.entry(g.as_str()).or_default().push(s);
+            }
+        }
+    }
+    let date = |d: Option<NaiveDate>| d.map(Value::Date).unwrap_or_else(|| text(""));
+    map(vec![
+        (
+            "sessions",
+            Value::List(all.iter().map(|s| session(s, rates)).collect()),
+        ),
+        (
+            "jobs",
+            totals(jobs, rates, |j| {
+                let r = rates.for_job(j).map(Value::Num);
+                vec![("name", text(j)), ("rate", r.unwrap_or_else(|| text("")))]
+            }),
+        ),
+        (
+            "days",
+            totals(days, rates, |d| vec![("date", Value::Date(*d))]),
+        ),
+        (
+            "groups",
+            totals(in_groups, rates, |g| vec![("name", text(g))]),
+        ),
+        ("start", date(all.iter().map(|s| s.0.date).min())),
+        ("end", date(all.iter().map(|s| s.0.date).max())),
+        ("total", Value::Time(total(&all))),
+        ("count", Value::Num(all.len() as f64)),
+        ("amount", amount(&all, rates)),
+    ])
+}
+
+fn filter(v: Value, f: &str) -> Result<Value, TokErr> {
+    let (name, arg) = match f.find(':') {
+        Some(n) => (f[..n].trim(), Some(&f[n + 1..])),
+        None => (f.trim(), None),
+    };
+    let width = || -> Result<usize, TokErr> {
+        arg.and_then(|a| a.trim().parse().ok())
+            .ok_or_else(|| TokErr::Mess(format!("Filter \"{}\" needs a width", name)))
+    };
+    Ok(match (name, v) {
+        ("hours", Value::Time(t)) => Value::Num(t.as_mins() as f64 / 60.),
+        ("mins", Value::Time(t)) => Value::Num(t.as_mins() as f64),
+        ("date", Value::Date(d)) => {
+            let fmt = arg.unwrap_or("%d/%m/%Y");
+            let mut res = String::new();
+            write!(res, "{}", d.format(fmt))
+                .map_err(|_| TokErr::Mess(format!("Bad date format \"{}\"", fmt)))?;
+            Value::Text(res)
+        }
+        ("money", Value::Num(n)) => Value::Text(rate::money(n)),
+        ("join", Value::List(l)) => {
+            let items: Vec<String> = l.iter().map(|v| v.to_string()).collect();
+            Value::Text(items.join(arg.unwrap_or(", ")))
+        }
+        ("upper", v) => Value::Text(v.to_string().to_uppercase()),
+        ("lower", v) => Value::Text(v.to_string().to_lowercase()),
+        ("left", v) => Value::Text(format!("{:<1$}", v.to_string(), width()?)),
+        ("right", v) => Value::Text(format!("{:>1$}", v.to_string(), width()?)),
+        ("default", v) => match v.truthy() {
+            true => v,
+            false => Value::Text(arg.unwrap_or("").to_string()),
+        },
+        ("money", Value::Text(s)) if s.is_empty() => Value::Text(s),
+        (_, v) => {
+            return Err(TokErr::Mess(format!(
+                "Filter \"{}\" cannot be used on \"{}\"",
+                f, v
+            )))
+        }
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Text(String),
+    Var(String, Vec<String>),
+    Each(String, Vec<Node>),
+    If(String, Vec<Node>, Vec<Node>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Text(String),
+    Tag(String),
+}
+
+impl Tok {
+    fn is_block(&self) -> bool {
+        match self {
+            Tok::Tag(t) => t.starts_with('#') || t.starts_with('/') || t == "else",
+            Tok::Text(_) => false,
+        }
+    }
+}
+
+fn tokens(s: &str) -> Result<Vec<Tok>, TokErr> {
+    let mut res = Vec::new();
+    let mut rest = s;
+    while let Some(n) = rest.find("{{") {
+        res.push(Tok::Text(rest[..n].to_string()));
+        let e = rest[n..].find("}}").ok_or_else(|| {
+            TokErr::Mess(format!(
+                "Unclosed \"{{{{\" in template near \"{}\"",
+                &rest[n..].lines().next().unwrap_or("")
+            ))
+        })?;
+        res.push(Tok::Tag(rest[n + 2..n + e].trim().to_string()));
+        rest = &rest[n + e + 2..];
+    }
+    res.push(Tok::Text(rest.to_string()));
+
+    // A block tag alone on its line takes the line with it
+    let orig = res.clone();
+    for i in 0..orig.len() {
+        if !orig[i].is_block() {
+            continue;
+        }
+        let before = match &orig[i - 1] {
+            Tok::Text(t) => match t.rfind('\n') {
+                Some(n) => t[n + 1..].trim().is_empty(),
+                None => i == 1 && t.trim().is_empty(),
+            },
+            _ => false,
+        };
+        let after = match &orig[i + 1] {
+            Tok::Text(t) => match t.find('\n') {
+                Some(n) => t[..n].trim().is_empty(),
+                None => i + 2 == orig.len() && t.trim().is_empty(),
+            },
+            _ => false,
+        };
+        if before && after {
+            if let Tok::Text(t) = &mut res[i - 1] {
+                t.truncate(t.rfind('\n').map(|n| n + 1).unwrap_or(0));
+            }
+            if let Tok::Text(t) = &mut res[i + 1] {
+                let n = t.find('\n').map(|n| n + 1).unwrap_or_else(|| t.len());
+                *t = t[n..].to_string();
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// Reads nodes up to the closing tag, returning the nodes and the tag that ended them
+fn nodes<I: Iterator<Item = Tok>>(it: &mut I, end: &str) -> Result<(Vec<Node>, String), TokErr> {
+    let mut res = Vec::new();
+    while let Some(t) = it.next() {
+        let tag = match t {
+            Tok::Text(s) if s.is_empty() => continue,
+            Tok::Text(s) => {
+                res.push(Node::Text(s));
+                continue;
+            }
+            Tok::Tag(tag) => tag,
+        };
+        if tag == "else" || tag.starts_with('/') {
+            if tag == end || (tag == "else" && end == "/if") {
+                return Ok((res, tag));
+            }
+            return Err(TokErr::Mess(format!(
+                "Unexpected \"{{{{{}}}}}\" in template",
+                tag
+            )));
+        }
+        if let Some(path) = tag.strip_prefix("#each ") {
+            let (body, _) = nodes(it, "/each")?;
+            res.push(Node::Each(path.trim().to_string(), body));
+        } else if let Some(path) = tag.strip_prefix("#if ") {
+            let (yes, close) = nodes(it, "/if")?;
+            let no = match close.as_ref() {
+                "else" => nodes(it, "/if")?.0,
+                _ => Vec::new(),
+            };
+            res.push(Node::If(path.trim().to_string(), yes, no));
+        } else if tag.starts_with('#') {
+            return Err(TokErr::Mess(format!(
+                "Unknown block \"{{{{{}}}}}\" in template",
+                tag
+            )));
+        } else {
+            let mut parts = tag.split('|').map(|p| p.trim().to_string());
+            let path = parts.next().unwrap_or_default();
+            res.push(Node::Var(path, parts.collect()));
+        }
+    }
+    match end {
+        "" => Ok((res, String::new())),
+        e => Err(TokErr::Mess(format!(
+            "Template is missing \"{{{{{}}}}}\"",
+            e
+        ))),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template(Vec<Node>);
+
+impl Template {
+    pub fn parse(s: &str) -> Result<Self, TokErr> {
+        Ok(Template(nodes(&mut tokens(s)?.into_iter(), "")?.0))
+    }
+
+    pub fn render(&self, ctx: &Value) -> Result<String, TokErr> {
+        let mut res = String::new();
+        render_nodes(&self.0, &mut vec![ctx.clone()], &mut res)?;
+        Ok(res)
+    }
+}
+
+/// Names are looked for in the innermost loop first, then outwards
+fn lookup(scopes: &[Value], path: &str) -> Result<Value, TokErr> {
+    let mut parts = path.split('.');
+    let first = parts.next().unwrap_or("");
+    let mut v = scopes
+        .iter()
+        .rev()
+        .find_map(|s| s.get(first))
+        .ok_or_else(|| TokErr::Mess(format!("Unknown template value \"{}\"", path)))?;
+    for p in parts {
+        v = v
+            .get(p)
+            .ok_or_else(|| TokErr::Mess(format!("Unknown template value \"{}\"", path)))?;
+    }
+    Ok(v)
+}
+
+fn render_nodes(nodes: &[Node], scopes: &mut Vec<Value>, res: &mut String) -> Result<(), TokErr> {
+    for n in nodes {
+        match n {
+            Node::Text(s) => res.push_str(s),
+            Node::Var(path, filters) => {
+                let mut v = lookup(scopes, path)?;
+                for f in filters {
+                    v = filter(v, f)?;
+                }
+                res.push_str(&v.to_string());
+            }
+            Node::If(path, yes, no) => match lookup(scopes, path)?.truthy() {
+                true => render_nodes(yes, scopes, res)?,
+                false => render_nodes(no, scopes, res)?,
+            },
+            Node::Each(path, body) => {
+                let items = match lookup(scopes, path)? {
+                    Value::List(l) => l,
+                    _ => return Err(TokErr::Mess(format!("\"{}\" is not a list", path))),
+                };
+                for (i, item) in items.into_iter().enumerate() {
+                    scopes.push(map(vec![
+                        ("this", item.clone()),
+                        ("@index", Value::Num((i + 1) as f64)),
+                    ]));
+                    scopes.push(item);
+                    let r = render_nodes(body, scopes, res);
+                    scopes.truncate(scopes.len() - 2);
+                    r?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The file in the templates folder with this name, with or without an extension
+pub fn find(dir: &Path, name: &str) -> Result<PathBuf, TokErr> {
+    let exact = dir.join(name);
+    if exact.is_file() {
+        return Ok(exact);
+    }
+    std::fs::read_dir(dir)
+        .ok()
+        .and_then(|rd| {
+            rd.filter_map(|e| e.ok().map(|e| e.path()))
+                .find(|p| p.is_file() && p.file_stem().map(|s| s == name).unwrap_or(false))
+        })
+        .ok_or_else(|| TokErr::Mess(format!("No template \"{}\" in {}", name, dir.display())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    pub fn template_loops_and_formats() {
+        let (data, sessions, _) = fixture::read(fixture::DEMO);
+        let mut rates = Rates::default();
+        rates.add("eat=10").unwrap();
+        let ctx = context(&sessions, &data.groups, &rates);
+        let t = Template::parse(
+            "Week of {{start|date:%d %b}}\n\
+             {{#each jobs}}\n\
+             {{@index}}. {{name|left:12}}{{time|hours}}h{{#if rate}} £{{amount|money}}{{/if}}\n\
+             {{/each}}\n\
+             {{#each groups}}{{name}} {{time}}{{/each}}, total {{total}}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            t.render(&ctx).unwrap(),
+            "Week of 23 Jan\n\
+             1. car_wash    2.33h\n\
+             2. eat         2h £20.00\n\
+             3. programming 3h\n\
+             home_jobs 04:20, total 07:20\n"
+        );
+        assert!(Template::parse("{{#each jobs}}{{name}}").is_err());
+        let bad = Template::parse("{{nmae}}").unwrap();
+        assert!(bad.render(&ctx).is_err());
+        let bad = Template::parse("{{start|date:%Q}}").unwrap();
+        assert!(bad.render(&ctx).is_err());
+    }
+}
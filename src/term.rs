@@ -0,0 +1,33 @@
+//! What little is needed from the terminal, through stty rather than a terminal library
+
+use crate::err::TokErr;
+use std::process::{Command, Stdio};
+
+pub fn stty(args: &[&str]) -> Result<String, TokErr> {
+    let out = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|e| TokErr::Mess(format!("Could not run stty: {}", e)))?;
+    match out.status.success() {
+        true => Ok(String::from_utf8_lossy(&out.stdout).trim().to_string()),
+        false => Err(TokErr::from("stty failed, is this a terminal?")),
+    }
+}
+
+fn env_num(k: &str) -> Option<usize> {
+    std::env::var(k).ok().and_then(|v| v.parse().ok())
+}
+
+/// Rows and columns of the terminal, from stty, then $LINES and $COLUMNS, or 24 by 80
+pub fn term_size() -> (usize, usize) {
+    stty(&["size"])
+        .ok()
+        .and_then(|s| {
+            let mut it = s.split_whitespace().map(|n| n.parse().ok());
+            Some((it.next()??, it.next()??))
+        })
+        .or_else(|| Some((env_num("LINES").unwrap_or(24), env_num("COLUMNS")?)))
+        .filter(|&(r, c)| r > 5 && c > 20)
+        .unwrap_or((24, 80))
+}
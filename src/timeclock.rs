@@ -0,0 +1,162 @@
+//! The timeclock format read by ledger and hledger:
+//!
+//! ```text
+//! ; tags: outside, quick
+//! i 2019/01/23 12:30:00 car:wash  optional note
+//! o 2019/01/23 13:50:00
+//! ```
+//!
+//! Jobs are accounts, with "/" in job names as ":" between account levels.
+//! Notes are the payee, and tags go in a comment just before the clock in.
+
+use crate::clockin::{InData, Session};
+use crate::gob::JOB_SEP;
+use crate::ics::at;
+use crate::s_time::STime;
+use chrono::naive::{NaiveDate, NaiveDateTime};
+
+fn stamp(dt: NaiveDateTime) -> String {
+    dt.format("%Y/%m/%d %H:%M:%S").to_string()
+}
+
+fn clock_in(d: &InData) -> String {
+    let mut res = String::new();
+    if !d.tags.is_empty() {
+        res.push_str(&format!("; tags: {}\n", d.tags.join(", ")));
+    }
+    res.push_str(&format!(
+        "i {} {}",
+        stamp(at(d.date, d.time)),
+        d.job.replace(JOB_SEP, ":")
+    ));
+    if let Some(n) = &d.note {
+        res.push_str(&format!("  {}", n));
+    }
+    res.push('\n');
+    res
+}
+
+/// A clock in and out for each session, the open session if given only clocks in
+pub fn export(sessions: &[Session], open: Option<&InData>) -> String {
+    let mut res = String::new();
+    for (d, out) in sessions {
+        res.push_str(&clock_in(d));
+        if Some(d) != open {
+            res.push_str(&format!("o {}\n", stamp(at(d.date, *out))));
+        }
+    }
+    res
+}
+
+fn read_stamp(date: &str, time: &str) -> Option<NaiveDateTime> {
+    let date = date.replace('-', "/");
+    let d = NaiveDate::parse_from_str(&date, "%Y/%m/%d").ok()?;
+    ["%H:%M:%S", "%H:%M"]
+        .iter()
+        .find_map(|f| chrono::NaiveTime::parse_from_str(time, f).ok())
+        .map(|t| d.and_time(t))
+}
+
+/// The sessions in the file, and a message for each line that could not be used
+pub fn import(s: &str) -> (Vec<Session>, Vec<String>) {
+    let mut res = Vec::new();
+    let mut errs = Vec::new();
+    let mut tags: Vec<String> = Vec::new();
+    let mut open: Option<(NaiveDateTime, InData, usize)> = None;
+    for (n, l) in s.lines().enumerate() {
+        let l = l.trim_end();
+        let mut bad = |m: &str| errs.push(format!("Line {}: {}", n + 1, m));
+        if let Some(c) = l.strip_prefix(';').or_else(|| l.strip_prefix('#')) {
+            if let Some(t) = c.trim().strip_prefix("tags:") {
+                tags = t
+                    .split(',')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect();
+            }
+            continue;
+        }
+        let mut parts = l.splitn(4, ' ');
+        let (code, date, time) = (parts.next(), parts.next(), parts.next());
+        let dt = match (date, time) {
+            (Some(d), Some(t)) => read_stamp(d, t),
+            _ => None,
+        };
+        match (code, dt) {
+            (None, _) | (Some(""), _) => {}
+            (Some("i"), Some(dt)) | (Some("I"), Some(dt)) => {
+                if open.is_some() {
+                    bad("clock in while already clocked in");
+                }
+                let rest = parts.next().unwrap_or("");
+                let (acc, note) = match rest.find("  ") {
+                    Some(i) => (&rest[..i], Some(rest[i..].trim().to_string())),
+                    None => (rest, None),
+                };
+                let job = acc.trim().replace(':', &JOB_SEP.to_string());
+                if job.is_empty() {
+                    bad("clock in has no account");
+                }
+                let date = dt.date();
+                open = Some((
+                    dt,
+                    InData {
+                        time: STime::new(0, (dt - date.and_hms(0, 0, 0)).num_minutes() as isize),
+                        job,
+                        tags: std::mem::take(&mut tags),
+                        date,
+                        line: 0,
+                        note: note.filter(|n| !n.is_empty()),
+                    },
+                    n,
+                ));
+            }
+            (Some("o"), Some(dt)) | (Some("O"), Some(dt)) => match open.take() {
+                Some((st, d, _)) if dt >= st && !d.job.is_empty() => {
+                    let out = d.time + STime::new(0, (dt - st).num_minutes() as isize);
+                    res.push((d, out));
+                }
+                Some((st, _, _)) if dt < st => bad("clock out before clock in"),
+                Some(_) => {}
+                None => bad("clock out without clock in"),
+            },
+            (Some("i"), None) | (Some("o"), None) | (Some("I"), None) | (Some("O"), None) => {
+                bad("could not read the date and time")
+            }
+            (Some(_), _) => bad("unknown line"),
+        }
+    }
+    if let Some((_, _, n)) = open {
+        errs.push(format!(
+            "Line {}: still clocked in at the end, left out",
+            n + 1
+        ));
+    }
+    (res, errs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    pub fn timeclock_round_trips() {
+        let s = "23/01/2019\n  _outside,car/wash,12:30-13:50,#soapy\n  eat,23:00-24:30\n";
+        let sessions = fixture::sessions(s);
+        let out = export(&sessions, None);
+        assert!(out.starts_with(
+            "; tags: outside\ni 2019/01/23 12:30:00 car:wash  soapy\no 2019/01/23 13:50:00\n"
+        ));
+        assert!(out.contains("o 2019/01/24 00:30:00\n"));
+        let (back, errs) = import(&format!("{}o 2019/01/25 10:00:00\n", out));
+        let lines: Vec<usize> = sessions.iter().map(|s| s.0.line).collect();
+        let back: Vec<Session> = back
+            .into_iter()
+            .zip(lines)
+            .map(|((d, o), line)| (InData { line, ..d }, o))
+            .collect();
+        assert_eq!(back, sessions);
+        assert_eq!(errs, vec!["Line 7: clock out without clock in"]);
+    }
+}
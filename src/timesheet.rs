@@ -0,0 +1,131 @@
+//! A grid of jobs against the days of the week, one for each week in the range
+
+use crate::clockin::Session;
+use crate::report::{cell, duration, week_of, Format, Table};
+use crate::s_time::STime;
+use chrono::naive::NaiveDate;
+use chrono::{Datelike, Duration};
+use std::collections::BTreeMap;
+
+const DAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Time per job per weekday, keyed by the Monday of each week with any sessions
+pub fn weeks(sessions: &[Session]) -> BTreeMap<NaiveDate, BTreeMap<String, [STime; 7]>> {
+    let mut res: BTreeMap<NaiveDate, BTreeMap<String, [STime; 7]>> = BTreeMap::new();
+    for s in sessions {
+        let days = res
+            .entry(week_of(s.0.date).0)
+            .or_default()
+            .entry(s.0.job.clone())
+            .or_insert([STime::new(0, 0); 7]);
+        days[s.0.date.weekday().num_days_from_monday() as usize] += duration(s);
+    }
+    res
+}
+
+fn week_rows(jobs: &BTreeMap<String, [STime; 7]>) -> (Vec<Vec<String>>, Vec<String>) {
+    let mut rows = Vec::new();
+    let mut totals = [STime::new(0, 0); 7];
+    for (job, days) in jobs {
+        let mut row = vec![job.clone()];
+        let mut tot = STime::new(0, 0);
+        for (i, d) in days.iter().enumerate() {
+            row.push(cell(*d));
+            tot += *d;
+            totals[i] += *d;
+        }
+        row.push(tot.to_string());
+        rows.push(row);
+    }
+    let mut foot = vec!["Total".to_string()];
+    foot.extend(totals.iter().map(|t| cell(*t)));
+    foot.push(
+        totals
+            .iter()
+            .fold(STime::new(0, 0), |a, b| a + *b)
+            .to_string(),
+    );
+    (rows, foot)
+}
+
+/// One table per week, or for csv a single table with the week as the first column
+pub fn render(sessions: &[Session], f: Format) -> String {
+    let weeks = weeks(sessions);
+    if f == Format::Csv {
+        let mut head = vec!["Week", "Job"];
+        head.extend(DAYS.iter());
+        head.push("Total");
+        let mut tab = Table::new(&head);
+        for (wk, jobs) in &weeks {
+            let (rows, foot) = week_rows(jobs);
+            for r in rows.into_iter().chain(std::iter::once(foot)) {
+                let mut row = vec![wk.format("%d/%m/%Y").to_string()];
+                row.extend(r);
+                tab.rows.push(row);
+            }
+        }
+        return tab.csv();
+    }
+
+    let mut res = String::new();
+    for (wk, jobs) in &weeks {
+        let title = format!(
+            "Week {} {} ({} - {})",
+            wk.iso_week().week(),
+            wk.iso_week().year(),
+            wk.format("%d/%m/%Y"),
+            (*wk + Duration::days(6)).format("%d/%m/%Y")
+        );
+        let mut head = vec!["Job".to_string()];
+        for (i, d) in DAYS.iter().enumerate() {
+            head.push(format!(
+                "{} {}",
+                d,
+                (*wk + Duration::days(i as i64)).format("%d/%m")
+            ));
+        }
+        head.push("Total".to_string());
+        let (rows, foot) = week_rows(jobs);
+        let tab = Table {
+            head,
+            rows,
+            foot: vec![foot],
+        };
+        match f {
+            Format::Markdown => res.push_str(&format!("### {}\n\n{}\n", title, tab.markdown())),
+            Format::Html => res.push_str(&format!("<h2>{}</h2>\n{}", title, tab.html())),
+            _ => res.push_str(&format!("{}\n{}\n", title, tab.text())),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    pub fn timesheet_totals_by_weekday() {
+        let w = weeks(&fixture::sessions(fixture::DEMO));
+        assert_eq!(w.len(), 1);
+        let jobs = &w[&NaiveDate::from_ymd(2019, 1, 21)];
+        //23rd was a Wednesday
+        assert_eq!(jobs["car_wash"][2], STime::new(2, 20));
+        assert_eq!(jobs["eat"][3], STime::new(1, 0));
+        let (_, foot) = week_rows(jobs);
+        assert_eq!(foot[8], "07:20");
+    }
+
+    #[test]
+    pub fn timesheet_week_crosses_the_year() {
+        let w = weeks(&fixture::sessions(fixture::EDGES));
+        assert_eq!(w.len(), 1);
+        let jobs = &w[&NaiveDate::from_ymd(2019, 12, 30)];
+        //over midnight stays on the day it started
+        assert_eq!(jobs["games"][0], STime::new(2, 45));
+        assert_eq!(jobs["work/client_b"][2], STime::new(0, 5));
+        let (_, foot) = week_rows(jobs);
+        assert_eq!(foot[8], "03:50");
+    }
+}
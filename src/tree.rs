@@ -0,0 +1,103 @@
+//! Job names as a hierarchy, "client/backend/api" is under "client/backend" and "client"
+
+use crate::clockin::Session;
+use crate::gob::JOB_SEP;
+use crate::report::{duration, Format, Table};
+use crate::s_time::STime;
+use std::collections::BTreeMap;
+
+/// The levels of a job name, ignoring empty ones
+pub fn levels(job: &str) -> Vec<&str> {
+    job.split(JOB_SEP).filter(|l| !l.is_empty()).collect()
+}
+
+/// Whether the job is root or anything below it
+pub fn in_subtree(job: &str, root: &str) -> bool {
+    let (j, r) = (levels(job), levels(root));
+    j.len() >= r.len() && j[..r.len()] == r[..]
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Node {
+    /// Time booked to exactly this job
+    pub own: STime,
+    /// Time booked to this job and everything under it
+    pub total: STime,
+    pub count: usize,
+    pub children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn add(&mut self, path: &[&str], t: STime) {
+        self.total += t;
+        self.count += 1;
+        match path.split_first() {
+            Some((f, rest)) => self.children.entry(f.to_string()).or_default().add(rest, t),
+            None => self.own += t,
+        }
+    }
+
+    fn push_rows(&self, path: &str, depth: usize, f: Format, tab: &mut Table) {
+        for (k, c) in &self.children {
+            let full = match path {
+                "" => k.clone(),
+                p => format!("{}{}{}", p, JOB_SEP, k),
+            };
+            let label = match f {
+                Format::Text => format!("{}{}", "  ".repeat(depth), k),
+                _ => full.clone(),
+            };
+            tab.rows.push(vec![
+                label,
+                c.total.to_string(),
+                c.own.to_string(),
+                c.count.to_string(),
+            ]);
+            c.push_rows(&full, depth + 1, f, tab);
+        }
+    }
+}
+
+/// Every job level with the time of everything under it rolled up
+pub fn tree(sessions: &[Session]) -> Node {
+    let mut root = Node::default();
+    for s in sessions {
+        root.add(&levels(&s.0.job), duration(s));
+    }
+    root
+}
+
+/// Indented for text, full job names for csv and markdown
+pub fn render(sessions: &[Session], f: Format) -> String {
+    let root = tree(sessions);
+    let mut tab = Table::new(&["Job", "Total", "Own", "Sessions"]);
+    root.push_rows("", 0, f, &mut tab);
+    tab.foot.push(vec![
+        "Total".to_string(),
+        root.total.to_string(),
+        String::new(),
+        root.count.to_string(),
+    ]);
+    tab.render(f)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    pub fn tree_rolls_up_children() {
+        let s = "23/01/2019\n  client/back/api,9:00-10:00\n  client/back,10:00-10:30\n  \
+                 client/front,11:00-12:00\n  clientb,13:00-13:15\n";
+        let sessions = fixture::sessions(s);
+        let root = tree(&sessions);
+        let client = &root.children["client"];
+        assert_eq!(client.total, STime::new(2, 30));
+        assert_eq!(client.own, STime::new(0, 0));
+        assert_eq!(client.children["back"].total, STime::new(1, 30));
+        assert_eq!(client.children["back"].own, STime::new(0, 30));
+        assert!(in_subtree("client/back/api", "client/back"));
+        assert!(!in_subtree("clientb", "client"));
+    }
+}
@@ -0,0 +1,325 @@
+//! A full screen view drawn with ansi escapes: the open session, totals, recent jobs and the log
+
+use crate::clockin::{InData, Session};
+use crate::err::TokErr;
+use crate::report::{duration, week_of};
+use crate::s_time::STime;
+use crate::term::stty;
+use chrono::naive::{NaiveDate, NaiveDateTime};
+use std::io::Read;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Jobs that can be picked with the number keys
+pub const MAX_RECENT: usize = 9;
+
+/// How long to wait for the rest of an escape sequence before taking it as the escape key
+const ESC_WAIT: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Enter,
+    Backspace,
+    Esc,
+    CtrlC,
+}
+
+/// The key starting with byte b, taking more bytes from next for escape sequences.
+/// A byte that turns out not to be part of the sequence is given back
+fn decode(b: u8, mut next: impl FnMut() -> Option<u8>) -> (Option<Key>, Option<u8>) {
+    let k = match b {
+        b'\r' | b'\n' => Key::Enter,
+        127 | 8 => Key::Backspace,
+        3 => Key::CtrlC,
+        27 => {
+            return match next() {
+                Some(b'[') => match next() {
+                    Some(b'A') => (Some(Key::Up), None),
+                    Some(b'B') => (Some(Key::Down), None),
+                    _ => (None, None),
+                },
+                back => (Some(Key::Esc), back),
+            }
+        }
+        b if b.is_ascii() => Key::Char(b as char),
+        _ => return (None, None),
+    };
+    (Some(k), None)
+}
+
+/// Keys read from stdin on their own thread, so the screen can redraw while waiting
+pub fn keys() -> mpsc::Receiver<Key> {
+    let (btx, brx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for b in std::io::BufReader::new(std::io::stdin()).bytes() {
+            match b {
+                Ok(b) if btx.send(b).is_ok() => {}
+                _ => return,
+            }
+        }
+    });
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut held = None;
+        loop {
+            let b = match held.take() {
+                Some(b) => b,
+                None => match brx.recv() {
+                    Ok(b) => b,
+                    Err(_) => return,
+                },
+            };
+            let (k, back) = decode(b, || brx.recv_timeout(ESC_WAIT).ok());
+            held = back;
+            if let Some(k) = k {
+                if tx.send(k).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// Keys without waiting for enter, no echo, and Ctrl-C as a key, until dropped
+pub struct Raw(String);
+
+impl Raw {
+    pub fn enter() -> Result<Self, TokErr> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        print!("\x1b[?1049h\x1b[?25l");
+        Ok(Raw(saved))
+    }
+}
+
+impl Drop for Raw {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        stty(&[&self.0]).ok();
+    }
+}
+
+/// The last few different jobs, most recent first
+pub fn recent_jobs(sessions: &[Session]) -> Vec<String> {
+    let mut res: Vec<String> = Vec::new();
+    for s in sessions.iter().rev() {
+        if !res.contains(&s.0.job) {
+            res.push(s.0.job.clone());
+            if res.len() == MAX_RECENT {
+                break;
+            }
+        }
+    }
+    res
+}
+
+/// Time since the clockin, to the second
+pub fn elapsed(open: &InData, now: NaiveDateTime) -> String {
+    let start = open.date.and_hms(0, 0, 0) + chrono::Duration::minutes(open.time.as_mins() as i64);
+    let secs = (now - start).num_seconds().max(0);
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+pub struct View<'a> {
+    pub file: &'a str,
+    /// Closed sessions, then the open one ending now
+    pub sessions: &'a [Session],
+    pub open: Option<&'a InData>,
+    pub now: NaiveDateTime,
+    /// How many sessions up from the latest the log starts
+    pub scroll: usize,
+    pub message: &'a str,
+    /// The job being typed, if any
+    pub input: Option<&'a str>,
+    pub size: (usize, usize),
+}
+
+fn total(sessions: &[Session], f: impl Fn(NaiveDate) -> bool) -> STime {
+    sessions
+        .iter()
+        .filter(|s| f(s.0.date))
+        .fold(STime::default(), |a, s| a + duration(s))
+}
+
+/// The line cut to fit the width, so it never wraps.
+/// Escape sequences take no room and are all kept, so colours still end
+fn clip(l: &str, cols: usize) -> String {
+    let mut res = String::new();
+    let (mut width, mut esc) = (0, false);
+    for c in l.chars() {
+        match (c, esc) {
+            ('\x1b', _) => esc = true,
+            (_, true) => esc = !c.is_ascii_alphabetic(),
+            _ if width < cols => width += 1,
+            _ => continue,
+        }
+        res.push(c);
+    }
+    res
+}
+
+pub fn draw(v: &View) -> String {
+    let (rows, cols) = v.size;
+    let today = v.now.date();
+    let (st, fin) = week_of(today);
+    let mut lines = vec![
+        format!("\x1b[1mwork_tock\x1b[0m  {}", v.file),
+        String::new(),
+    ];
+    lines.push(match v.open {
+        Some(o) => format!(
+            "Clocked in to \x1b[1;32m{}\x1b[0m since {}  \x1b[1m{}\x1b[0m{}",
+            o.job,
+            o.time,
+            elapsed(o, v.now),
+            match o.tags.is_empty() {
+                true => String::new(),
+                false => format!("  [{}]", o.tags.join(", ")),
+            }
+        ),
+        None => "\x1b[33mNot clocked in\x1b[0m".to_string(),
+    });
+    lines.push(format!(
+        "Today {}   This week {}",
+        total(v.sessions, |d| d == today),
+        total(v.sessions, |d| d >= st && d <= fin)
+    ));
+    lines.push(String::new());
+    let recent: Vec<String> = recent_jobs(v.sessions)
+        .iter()
+        .enumerate()
+        .map(|(i, j)| format!("\x1b[1m{}\x1b[0m {}", i + 1, j))
+        .collect();
+    lines.push(format!("Switch to: {}", recent.join("  ")));
+    lines.push(
+        "\x1b[2m1-9 switch   i new job   o clock out   up/down scroll   q quit\x1b[0m".to_string(),
+    );
+    lines.push(String::new());
+
+    // The log takes what is left, less the prompt line
+    let room = rows.saturating_sub(lines.len() + 2).max(1);
+    let end = v.sessions.len().saturating_sub(v.scroll);
+    let start = end.saturating_sub(room);
+    let mut last_date = None;
+    for s in &v.sessions[start..end] {
+        let date = match last_date == Some(s.0.date) {
+            true => "          ".to_string(),
+            false => s.0.date.format("%d/%m/%Y").to_string(),
+        };
+        last_date = Some(s.0.date);
+        let mut l = format!(
+            "{}  {}-{}  {}  {}",
+            date,
+            s.0.time,
+            s.1,
+            duration(s),
+            s.0.job
+        );
+        if !s.0.tags.is_empty() {
+            l.push_str(&format!(" [{}]", s.0.tags.join(", ")));
+        }
+        lines.push(l);
+    }
+    //a small terminal loses the bottom of the header rather than the prompt
+    lines.truncate(rows - 1);
+    while lines.len() < rows - 1 {
+        lines.push(String::new());
+    }
+    lines.push(match v.input {
+        Some(i) => format!("\x1b[?25hJob: {}", i),
+        None => format!("\x1b[?25l{}", v.message),
+    });
+    let lines: Vec<String> = lines.iter().map(|l| clip(l, cols)).collect();
+    // Each line is cleared as it is written over, so the redraw does not flicker
+    format!("\x1b[H{}\x1b[K", lines.join("\x1b[K\r\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    pub fn view_shows_timer_and_recent_jobs() {
+        let (_, mut sessions, open) = fixture::read(fixture::DEMO);
+        assert_eq!(
+            recent_jobs(&sessions),
+            vec!["programming", "eat", "car_wash"]
+        );
+        let open = open.unwrap_or_else(|| sessions.last().unwrap().0.clone());
+        let now = open.date.and_hms(0, 0, 0)
+            + chrono::Duration::minutes(open.time.as_mins() as i64 + 61)
+            + chrono::Duration::seconds(5);
+        assert_eq!(elapsed(&open, now), "01:01:05");
+        sessions.push((open.clone(), open.time + STime::new(1, 1)));
+        let screen = draw(&View {
+            file: "demo1.wt",
+            sessions: &sessions,
+            open: Some(&open),
+            now,
+            scroll: 0,
+            message: "",
+            input: None,
+            size: (24, 80),
+        });
+        assert!(screen.contains("01:01:05"));
+        assert_eq!(screen.matches("\r\n").count(), 23);
+    }
+
+    #[test]
+    pub fn view_copes_with_little_room() {
+        let (_, sessions, open) = fixture::read(fixture::EDGES);
+        assert_eq!(
+            recent_jobs(&sessions),
+            vec!["work/client_b", "work/client_a", "walk", "games"]
+        );
+        let open = open.unwrap();
+        let screen = draw(&View {
+            file: "edges.wt",
+            sessions: &sessions,
+            open: Some(&open),
+            now: open.date.succ().and_hms(0, 0, 30),
+            scroll: sessions.len() + 3,
+            message: "",
+            input: None,
+            size: (6, 48),
+        });
+        assert!(screen.contains("00:01:30"));
+        assert_eq!(screen.matches("\r\n").count(), 5);
+    }
+
+    #[test]
+    pub fn lines_fit_the_width() {
+        assert_eq!(clip("\x1b[1mwork\x1b[0m_tock", 6), "\x1b[1mwork\x1b[0m_t");
+        assert_eq!(clip("\x1b[2mabc\x1b[0m", 1), "\x1b[2ma\x1b[0m");
+        let (_, sessions, open) = fixture::read(fixture::DEMO);
+        let screen = draw(&View {
+            file: "a/long/path/to/the/demo1.wt",
+            sessions: &sessions,
+            open: open.as_ref(),
+            now: sessions[0].0.date.and_hms(18, 0, 0),
+            scroll: 0,
+            message: "a message too long to fit",
+            input: None,
+            size: (24, 20),
+        });
+        //clipped to nothing, only the escapes are left
+        let width = |l: &str| l.chars().count() - clip(l, 0).chars().count();
+        assert!(screen.split("\r\n").all(|l| width(l) <= 20));
+        assert_eq!(screen.matches("\r\n").count(), 23);
+    }
+
+    #[test]
+    pub fn lone_escape_keeps_the_next_key() {
+        let mut rest = vec![b'q'].into_iter();
+        assert_eq!(decode(27, || rest.next()), (Some(Key::Esc), Some(b'q')));
+        assert_eq!(decode(27, || None), (Some(Key::Esc), None));
+        let mut rest = b"[A".iter().copied();
+        assert_eq!(decode(27, || rest.next()), (Some(Key::Up), None));
+        assert_eq!(decode(3, || None), (Some(Key::CtrlC), None));
+    }
+}